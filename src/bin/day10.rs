@@ -1,140 +1,370 @@
-use std::str::FromStr;
-use std::vec::IntoIter;
+use std::io::BufRead;
+use bracket_lib::prelude::*;
+use advent2022::{
+    app::{App, AppLevel, State},
+    emulator::{Cpu, InstructionSet, Status, asm, crt::Crt, debug::{self, Debugger, Stop}}
+};
 
-type Cycles = usize;
+fn main() -> BResult<()> {
+    let input = std::fs::read_to_string("src/bin/day10_input.txt").expect("Ops!");
 
-#[derive(Debug,Copy, Clone)]
-enum InstructionSet { Noop, AddX(isize) }
+    let sample_intervals = [20usize, 60, 100, 140, 180, 220];
 
-#[derive(Debug,Copy, Clone)]
-struct Instruction {
-    op: InstructionSet,
-    ticks: Cycles
-}
-impl Instruction {
-    fn result(&self) -> isize {
-        match self.op {
-            InstructionSet::Noop => 0,
-            InstructionSet::AddX(val) => val
+    let mut crt = Crt::day10();
+    let set = InstructionSet::day10();
+    let program = asm::assemble(&set, input.as_str()).unwrap_or_else(|e| panic!("{e}"));
+    let mut cpu = Cpu::new(set);
+    cpu.load(program);
+
+    let mut sum = 0;
+    while cpu.tick() == Status::Running {
+        let x = cpu.reg("x").unwrap();
+        crt.tick(x);
+        if sample_intervals.contains(&cpu.cycle()) {
+            sum += x * cpu.cycle() as isize;
         }
     }
-}
 
-#[derive(Debug)]
-struct Register(isize);
+    println!("{sum} is the sum of  signal strengths at {:?}", sample_intervals);
+    println!("Program halted after {} cycles", cpu.cycle());
+    println!("{}CRT reads: {}", crt.text(), crt.ocr());
 
+    let args = std::env::args().collect::<Vec<_>>();
+    let values = |name: &str| args.windows(2).filter(|w| w[0] == name).map(|w| w[1].clone()).collect::<Vec<_>>();
+    let flag = |name: &str| args.iter().any(|a| a == name);
 
-struct CPU {
-    x: Register,
-    buffer: Option<Instruction>,
-    exec_cycles: Cycles,
-    ip: Option<IntoIter<Instruction>>
-}
-impl CPU {
-    fn new() -> CPU {
-        CPU { x: Register(1), buffer: None, exec_cycles: 0, ip: None }
-    }
-    fn load(&mut self, ops: Vec<Instruction>) {
-        self.ip = Some(ops.into_iter());
-    }
-    fn fetch(&mut self, op: Instruction) {
-        self.exec_cycles = op.ticks;
-        self.buffer = Some(op);
-    }
-    fn execute(&mut self) -> bool {
-        match self.buffer {                         // Check instruction buffer
-            None => false,                          // empty, not exec, go and load
-            Some(op) => {                 // Instruction loaded
-                self.exec_cycles -= 1;               // execution cycle #
-                if self.exec_cycles == 0 {           // exec cycles reached ?
-                    self.x.0 += op.result();            // move Val to Reg X
-                    self.buffer = None;                 // flush instruction buffer
-                    false                           // not exec, go and load
-                } else { true }                     // Busy executing
+    let set = InstructionSet::extended();
+    let program = asm::assemble(&set, input.as_str()).unwrap_or_else(|e| panic!("{e}"));
+    if flag("--disasm") {
+        print!("{}", asm::disassemble(&set, &program));
+    }
+    values("--validate").iter()
+        .for_each(|path| {
+            let src = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("{path}: {e}"));
+            let errors = asm::validate(&set, src.as_str());
+            println!("{path}: {} problems", errors.len());
+            errors.iter().for_each(|e| println!("  {e}"));
+        });
+    let max_cycles = values("--max-cycles").first()
+        .map_or(Ok(100000), |v| v.parse())
+        .unwrap_or_else(|e| panic!("--max-cycles: {e}"));
+    let truncated = |halted: bool, name: &str| if !halted { println!("{name} still running after {max_cycles} cycles, trace cut short") };
+    if flag("--trace") {
+        let (trace, halted) = debug::trace(&set, program.clone(), max_cycles);
+        trace.iter().for_each(|t| println!("{t}"));
+        truncated(halted, "program");
+    }
+    if let Some(other) = values("--diff").first() {
+        let src = std::fs::read_to_string(other).unwrap_or_else(|e| panic!("{other}: {e}"));
+        let theirs = asm::assemble(&set, src.as_str()).unwrap_or_else(|e| panic!("{other}: {e}"));
+        let (a, halted) = debug::trace(&set, program.clone(), max_cycles);
+        truncated(halted, "program");
+        let (b, halted) = debug::trace(&set, theirs, max_cycles);
+        truncated(halted, other);
+        let diffs = debug::diff(&a, &b);
+        println!("{} cycles differ from {other}", diffs.len());
+        diffs.iter()
+            .for_each(|(cycle, a, b)| {
+                let show = |t: &Option<&debug::Trace>| t.map_or("<halted>".to_string(), |t| t.to_string());
+                println!("@{cycle}\n  < {}\n  > {}", show(a), show(b));
+            });
+    }
+    let breakpoints = values("--break");
+    if flag("--debug") || !breakpoints.is_empty() {
+        let mut cpu = Cpu::new(set.clone());
+        cpu.load(program.clone());
+        let mut dbg = Debugger::new(cpu);
+        breakpoints.iter()
+            .for_each(|bp| { dbg.add_breakpoint(bp.parse().unwrap_or_else(|e| panic!("{e}"))); });
+        if flag("--debug") {
+            debug_session(dbg);
+        } else {
+            while let Stop::Breakpoint(i) = dbg.resume() {
+                println!("break {i} ({}): {}", breakpoints[i], debug::Trace::capture(dbg.cpu()));
             }
         }
     }
-    fn tick(&mut self) {
-        if !self.execute() {
-            let mut ip = self.ip.take().unwrap();
-            self.fetch(ip.next().unwrap());
-            self.ip.replace(ip);
+
+    if !flag("--animate") {
+        return Ok(())
+    }
+    let ctx = BTermBuilder::simple(84, 30)?
+        .with_simple_console_no_bg(84, 30, "terminal8x8.png")
+        .with_fps_cap(60f32)
+        .with_title("Day10: Cathode-Ray Tube")
+        .build()?;
+
+    let mut cpu = Cpu::new(set);
+    cpu.load(program);
+    let mut app = App::init(Store { cpu, crt: Crt::day10() }, Levels::Screen);
+    app.register_level(Levels::Screen, Screen { paused: false, rate: 0, frame: 0 });
+
+    main_loop(ctx, app)
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+enum Levels { Screen }
+
+struct Store {
+    cpu: Cpu,
+    crt: Crt
+}
+impl Store {
+    /// Run one CPU cycle and draw its pixel; false once the program has halted
+    fn cycle(&mut self) -> bool {
+        let running = self.cpu.tick() == Status::Running;
+        if running {
+            self.crt.tick(self.cpu.reg("x").unwrap());
         }
+        running
     }
-    fn reg_x(&self) -> isize {
-        self.x.0
+    fn restart(&mut self) {
+        self.cpu.reset();
+        self.crt.clear();
+    }
+    /// Each CRT pixel as a 2x2 block under a row marking the sprite window; the beam shows on the pixel to be drawn next
+    fn draw(&self, ctx: &mut BTerm) {
+        let (left, top) = (2, 4);
+        let screen = &self.crt.screen;
+        let beam = self.crt.beam();
+        let sprite = self.crt.sprite(self.cpu.reg("x").unwrap());
+
+        ctx.cls();
+        (0..screen.height)
+            .flat_map(|y| (0..screen.width).map(move |x| (x, y)))
+            .for_each(|(x, y)| {
+                let p = (x, y).into();
+                let fg = match *screen.square(p).unwrap() {
+                    _ if p == beam => RED,
+                    true => YELLOW,
+                    false => GREY15
+                };
+                let (cx, cy) = (left + 2 * x, top + 2 * y);
+                [(0, 0), (1, 0), (0, 1), (1, 1)].into_iter()
+                    .for_each(|(dx, dy)| ctx.set(cx + dx, cy + dy, fg, BLACK, to_cp437('\u{2588}')));
+            });
+        (0..screen.width)
+            .filter(|&x| sprite.contains(&(x as isize)))
+            .for_each(|x| {
+                [0, 1].into_iter()
+                    .for_each(|dx| ctx.set(left + 2 * x + dx, top - 2, CYAN, BLACK, to_cp437('\u{2580}')));
+            });
     }
 }
 
-struct CRT {
-    width: usize,
-    clock: Cycles
+/// Drives CPU and CRT in step; `rate` is the power of two of cycles per frame, negative values slow down to
+/// one cycle every so many frames. `Space` pauses, `N` steps a cycle, `Up`/`Down` change speed and `R` restarts
+struct Screen {
+    paused: bool,
+    rate: i32,
+    frame: usize
 }
-impl CRT {
-    fn new(width: usize) -> CRT {
-        CRT{ width, clock: 0 }
+impl AppLevel for Screen {
+    type GStore = Store;
+    type GLevel = Levels;
+
+    fn init(&mut self, ctx: &mut BTerm, store: &mut Self::GStore) -> (Self::GLevel, State) {
+        store.restart();
+        self.paused = false;
+        ctx.set_active_console(0);
+        store.draw(ctx);
+        (Levels::Screen, State::RUN)
     }
-    fn draw(&mut self, pos: isize) {
-        let col = self.clock % self.width;
-        print!("{}",
-            if (pos-1..=pos+1).contains(&(col as isize)) { '#' } else { '.' }
-        );
-        if col == self.width-1 { println!() }
+    fn run(&mut self, ctx: &mut BTerm, store: &mut Self::GStore) -> (Self::GLevel, State) {
+        match ctx.key {
+            Some(VirtualKeyCode::Space) => self.paused = !self.paused,
+            Some(VirtualKeyCode::N) if self.paused => { store.cycle(); },
+            Some(VirtualKeyCode::Up) => self.rate = (self.rate + 1).min(8),
+            Some(VirtualKeyCode::Down) => self.rate = (self.rate - 1).max(-6),
+            Some(VirtualKeyCode::R) => return (Levels::Screen, State::INIT),
+            _ => {}
+        }
+        self.frame += 1;
+        if !self.paused && self.frame.is_multiple_of(1 << (-self.rate).max(0))
+            && !(0..1 << self.rate.max(0)).all(|_| store.cycle()) {
+            self.paused = true;
+        }
+
+        ctx.set_active_console(0);
+        store.draw(ctx);
+
+        let cpu = &store.cpu;
+        let instruction = cpu.current().map_or("-".to_string(), |ins| cpu.instruction_set().format(ins));
+        let speed = match self.rate {
+            r if r >= 0 => format!("{} cycles/frame", 1 << r),
+            r => format!("1 cycle/{} frames", 1 << -r)
+        };
+        ctx.set_active_console(1);
+        ctx.cls();
+        ctx.print(0, 0, format!("FPS: {}", ctx.fps));
+        ctx.print(2, 18, format!("Cycle: {:<5} Beam: {:?}", cpu.cycle(), store.crt.beam()));
+        ctx.print(2, 19, format!("Instruction: {instruction:<12} X: {}", cpu.reg("x").unwrap()));
+        ctx.print(2, 20, format!("Speed: {speed}{}", match (cpu.is_halted(), self.paused) {
+            (true, _) => "  [halted]",
+            (_, true) => "  [paused]",
+            _ => ""
+        }));
+        if cpu.is_halted() {
+            ctx.print(2, 22, format!("CRT reads: {}", store.crt.ocr()));
+        }
+        ctx.print(2, 28, "Space: Pause, N: Step, Up/Down: Speed, R: Restart, Q: Quit");
+        (Levels::Screen, State::RUN)
     }
-    fn tick(&mut self, pos:isize) {
-        self.draw(pos);
-        self.clock += 1;
+    fn term(&mut self, ctx: &mut BTerm, _: &mut Self::GStore) -> (Self::GLevel, State) {
+        ctx.quit();
+        (Levels::Screen, State::FINISH)
     }
 }
 
-fn parse_instructions(inp: &str) -> (Vec<Instruction>, usize) {
-    inp.lines()
-        .map(|line| {
-            let mut iter = line.split(' ');
-            match iter.next() {
-                Some("noop") => Instruction { op: InstructionSet::Noop, ticks: 1 },
-                Some("addx") => {
-                    let val = isize::from_str(
-                        iter.next().expect("parse_instructions: addx is missing its value!")
-                    ).expect("parse_instructions: addx not followed by numeric value!");
-                    Instruction { op: InstructionSet::AddX(val), ticks: 2 }
-                },
-                _ => panic!("parse_instructions: unknown instruction caught!")
-            }
-        })
-        .fold((vec![],0), |(mut out,mut total), op| {
-            total += op.ticks;
-            out.push(op);
-            (out,total)
-        })
+/// Interactive debugger over stdin; `s` step, `c` continue, `b <spec>` add and `d <n>` delete a breakpoint,
+/// `l` list breakpoints, `p` print state, `q` quit
+fn debug_session(mut dbg: Debugger) {
+    println!("debugger: s(tep), c(ontinue), b(reak) <@cycle|reg op value>, d(elete) <n>, l(ist), p(rint), q(uit)");
+    for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+        let (cmd, arg) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        let stop = match cmd {
+            "s" | "" => Some(dbg.step()),
+            "c" => Some(dbg.resume()),
+            "b" => match arg.parse() {
+                Ok(bp) => { println!("breakpoint {}", dbg.add_breakpoint(bp)); None },
+                Err(e) => { println!("{e}"); None }
+            },
+            "d" => {
+                match arg.parse().ok().and_then(|i| dbg.remove_breakpoint(i)) {
+                    Some(bp) => println!("deleted {bp}"),
+                    None => println!("no breakpoint '{arg}'")
+                }
+                None
+            },
+            "l" => { dbg.breakpoints().enumerate().for_each(|(i, bp)| println!("{i}: {bp}")); None },
+            "p" => { println!("{}", debug::Trace::capture(dbg.cpu())); None },
+            "q" => break,
+            _ => { println!("unknown command '{cmd}'"); None }
+        };
+        match stop {
+            Some(Stop::Halted) => { println!("halted after {} cycles", dbg.cpu().cycle()); break }
+            Some(Stop::Breakpoint(i)) => println!("break {i}: {}", debug::Trace::capture(dbg.cpu())),
+            Some(Stop::Step) => println!("{}", debug::Trace::capture(dbg.cpu())),
+            None => {}
+        }
+    }
 }
 
-fn main() {
-    let input = std::fs::read_to_string("src/bin/day10_input.txt").expect("Ops!");
+#[cfg(test)]
+mod test {
+    use super::*;
+    use advent2022::emulator::{Arg, AsmError, Machine, Operand};
 
-    let sample_intervals = vec![20usize, 60, 100, 140, 180, 220];
-    let mut sampling_interval = sample_intervals.iter().peekable();
-
-    let mut crt = CRT::new(40);
-    let mut cpu = CPU::new();
-
-    let (opcode, clock) = parse_instructions(input.as_str() );
-    cpu.load(opcode);
-
-    let sum = (1..=clock)
-        .map(|cycle| {
-            cpu.tick();
-            crt.tick(cpu.reg_x());
-            ( cycle, cpu.reg_x() )
-        })
-        .filter(|(cycle,_)|
-            match sampling_interval.peek() {
-                Some(&to_sample) if to_sample.eq(cycle) => { sampling_interval.next(); true }
-                _ => false
-            }
-        )
-        .map(|(clock, x)| x * clock as isize)
-        .sum::<isize>();
+    #[test]
+    fn test_small_program() {
+        let set = InstructionSet::day10();
+        let mut cpu = Cpu::new(set.clone());
+        cpu.load(set.parse("noop\naddx 3\naddx -5").unwrap());
+        let trace = std::iter::from_fn(|| (cpu.tick() == Status::Running).then(|| cpu.reg("x").unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(trace, vec![1, 1, 1, 4, 4]);
+        assert_eq!(cpu.reg("x"), Some(-1));
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.tick(), Status::Halted);
+        assert_eq!(cpu.cycle(), 5);
+    }
+    #[test]
+    fn test_extended_set() {
+        let set = InstructionSet::extended();
+        // jump over the second mul, then off the start of the program
+        let program = set.parse("addx 1\nmul x 3\njmp 2\nmul x 10\naddy 4\njmp -9").unwrap();
+        let mut cpu = Cpu::new(set.clone());
+        cpu.load(program);
+        assert_eq!(cpu.run(), 9);
+        assert_eq!((cpu.reg("x"), cpu.reg("y")), (Some(6), Some(4)));
 
-    println!("{sum} is the sum of  signal strengths at {:?}", sample_intervals);
-}
\ No newline at end of file
+        let set = set.define("swap", &[Arg::Reg, Arg::Reg], 1, |m: &mut Machine, a: &[Operand]| {
+            let (p, q) = (m.get(a[0]), m.get(a[1]));
+            m.set(a[0], q);
+            m.set(a[1], p);
+        });
+        let mut cpu = Cpu::new(set.clone());
+        cpu.load(set.parse("addy 7\nswap x y").unwrap());
+        assert_eq!(cpu.run(), 3);
+        assert_eq!((cpu.reg("x"), cpu.reg("y")), (Some(7), Some(1)));
+
+        assert!(set.parse("addx").is_err());
+        assert!(set.parse("mul 2 x").is_err());
+        assert_eq!(set.parse("noop\nfoo 1").map_err(|e| e.to_string()), Err("line 2: unknown mnemonic 'foo'".to_string()));
+    }
+    #[test]
+    fn test_crt() {
+        // the puzzle's larger example draws a fixed pattern; every other column lit on the first row
+        let program = [
+            "addx 15", "addx -11", "addx 6", "addx -3", "addx 5", "addx -1", "addx -8", "addx 13", "addx 4", "noop", "addx -1"
+        ].join("\n");
+        let set = InstructionSet::day10();
+        let mut cpu = Cpu::new(set.clone());
+        cpu.load(set.parse(&program).unwrap());
+        let mut crt = Crt::day10();
+        while cpu.tick() == Status::Running {
+            crt.tick(cpu.reg("x").unwrap());
+        }
+        assert_eq!(&crt.text()[..21], "##..##..##..##..##..#");
+        assert_eq!(crt.beam(), (cpu.cycle(), 0).into());
+
+        let mut wide = Crt::new(8, 2, 5);
+        assert_eq!(wide.sprite(3), 1..=5);
+        (0..16).for_each(|_| { wide.tick(3); });
+        assert_eq!(wide.text(), ".#####..\n.#####..\n");
+        assert_eq!(wide.beam(), (0, 0).into());
+    }
+    #[test]
+    fn test_assembler() {
+        let set = InstructionSet::extended();
+        let src = "; count y down to zero, doubling x\n\
+                   N = 3\n\
+                   addy N\n\
+                   loop: mul x 2\n\
+                   addy -1\n\
+                   jmp loop ; no conditional jumps, so this never ends";
+        let program = asm::assemble(&set, src).unwrap();
+        assert_eq!(program[3].args, vec![advent2022::emulator::Operand::Imm(-2)]);
+        assert_eq!(asm::assemble(&set, &asm::disassemble(&set, &program)), Ok(program));
+
+        let errors = asm::validate(&set, "addx 1\nfrob 2\nx = 4\nlbl: noop\nlbl: jmp nowhere\nK = L\nmul 3 4");
+        assert_eq!(errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(), vec![
+            "line 2: unknown mnemonic 'frob'",
+            "line 3: 'x' is already defined",
+            "line 5: 'lbl' is already defined",
+            "line 5: invalid operand 'nowhere'",
+            "line 6: invalid operand 'L'",
+            "line 7: invalid operand '3'",
+        ]);
+
+        // labels stand for jump offsets only; constants go anywhere
+        let errors = asm::validate(&set, "N = 2\nloop: addx loop\nmul x loop\nmul x N\njmp loop");
+        assert_eq!(errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(), vec![
+            "line 2: label 'loop' used where a jump target is not expected",
+            "line 3: label 'loop' used where a jump target is not expected",
+        ]);
+        assert_eq!(asm::assemble(&set, "loop: addx loop"), Err(AsmError::MisplacedLabel { line: 1, name: "loop".to_string() }));
+    }
+    #[test]
+    fn test_trace_diff() {
+        let set = InstructionSet::extended();
+        let (a, _) = debug::trace(&set, set.parse("noop\naddx 3\naddy 1").unwrap(), 100);
+        let (b, _) = debug::trace(&set, set.parse("noop\naddx 3\naddy 2\nnoop").unwrap(), 100);
+        assert_eq!(a[2].to_string(), "     3    1: addx 3           +0 x=1 y=0");
+        let diffs = debug::diff(&a, &b);
+        assert_eq!(diffs.iter().map(|&(c, ..)| c).collect::<Vec<_>>(), vec![4, 5, 6]);
+        assert!(diffs[2].1.is_none());
+
+        // a program jumping onto itself never halts; its trace stops at the limit
+        let src = "start: noop\njmp start";
+        let (looping, halted) = debug::trace(&set, asm::assemble(&set, src).unwrap(), 1000);
+        assert_eq!((looping.len(), halted), (1000, false));
+        assert_eq!(looping[999].instruction.as_deref(), Some("jmp -1"));
+        assert_eq!(debug::diff(&a, &looping).len(), 1000 - 1);
+
+        let bp = "y>=2".parse::<debug::Breakpoint>().unwrap();
+        assert_eq!(bp.to_string(), "y >= 2");
+        assert_eq!("@20".parse(), Ok(debug::Breakpoint::Cycle(20)));
+        assert!("x ~ 2".parse::<debug::Breakpoint>().is_err());
+    }
+}
@@ -1,181 +1,817 @@
-use std::cell::Cell;
-use std::collections::VecDeque;
-use std::ops::{Add, Mul};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::Hash;
+use std::io::{BufWriter, Write};
 use std::str::FromStr;
+use num_bigint::BigUint;
+use num_integer::Integer;
 
 fn main() {
 
     let input = std::fs::read_to_string("src/bin/day11_input.txt").expect("Ops!");
 
-    let mut monkeys = Monkey::parse_text(input.as_str());
-    let div_product: WorryType = monkeys.iter().map(|m| m.test).product();
+    let args = std::env::args().collect::<Vec<_>>();
+    let value = |name: &str| args.windows(2).find(|w| w[0] == name).map(|w| w[1].clone());
 
-    // Queue for passing items around the monkeys
-    let mut queue = vec![VecDeque::<WorryType>::new(); monkeys.len()];
+    let run = |relief: ReliefPolicy, rounds: usize| {
+        let business = monkey_business(input.as_str(), relief, rounds).unwrap_or_else(|e| panic!("{e}"));
+        println!("level of monkey business after {rounds} rounds with {relief} relief : {business}");
+    };
+    run(ReliefPolicy::Divide(3), 20);
+    run(ReliefPolicy::ModuloLcm, 10000);
 
-    (0..10000).all(|_| {
-        monkeys.iter_mut()
-            .map(|monkey| {
+    // custom experiment, e.g. --relief none --rounds 50
+    let relief = value("--relief").map_or(Ok(ReliefPolicy::ModuloLcm), |r| r.parse()).unwrap_or_else(|e| panic!("--relief: {e}"));
+    let rounds = value("--rounds").map_or(Ok(relief.rounds()), |r| r.parse()).unwrap_or_else(|e| panic!("--rounds: {e}"));
+    if value("--relief").is_some() || value("--rounds").is_some() {
+        run(relief, rounds);
+    }
 
-                // pull from queue anything thrown at him
-                while let Some(item) = queue[monkey.name].pop_front() {
-                    monkey.catch(item)
-                };
+    // skip ahead once round states repeat, e.g. --fast-forward 1000000000000
+    if let Some(target) = value("--fast-forward") {
+        let target = target.parse::<u64>().unwrap_or_else(|e| panic!("--fast-forward: {e}"));
+        let forecast = forecast(input.as_str(), relief, target).unwrap_or_else(|e| panic!("{e}"));
+        print!("level of monkey business after {target} rounds with {relief} relief : {}", forecast.monkey_business());
+        match forecast.period {
+            Some((start, length)) => println!(" (states repeat every {length} rounds from round {start})"),
+            None => println!(" (no repeating state)")
+        }
+    }
 
-                // observe and throw back at
-                monkey.observe_all(div_product)
-                    .into_iter()
-                    // .filter_map(|throw| throw)
-                    .all(|throw|
-                        throw.map(
-                            |(monkey,item)| queue[monkey].push_back(item)
-                        ).is_some()
-                    )
-            })
-            .all(|run| run)
-    });
+    // follow every item around; export queue snapshots per round and report items going round in circles
+    if args.iter().any(|arg| arg == "--track") {
+        match relief {
+            ReliefPolicy::None => track::<BigUint>(input.as_str(), relief, rounds),
+            _ => track::<WorryType>(input.as_str(), relief, rounds)
+        }
+            .unwrap_or_else(|e| panic!("{e}"));
+    }
+}
+
+/// Follow every item for a number of rounds, export the queues after each round and every item's lineage
+/// to `target/day11`, then list the items whose paths loop
+fn track<W: Worry>(input: &str, relief: ReliefPolicy, rounds: usize) -> Result<(), String> {
+    let sim = Simulation::<W>::new(Monkey::parse_text(input)?, relief)?
+        .tracking()
+        .run(rounds)?;
 
-    monkeys.sort_by(|a,b| b.inspect.cmp(&a.inspect));
-    println!("level of monkey business after 10000 rounds : {:?}",
-             monkeys[0].inspections() * monkeys[1].inspections()
-    );
+    let dir = "target/day11";
+    let create = |name: &str| std::fs::File::create(format!("{dir}/{name}")).map(BufWriter::new);
+    std::fs::create_dir_all(dir)
+        .and_then(|_| sim.write_snapshots_csv(create("snapshots.csv")?))
+        .and_then(|_| sim.write_snapshots_json(create("snapshots.json")?))
+        .and_then(|_| {
+            let mut out = create("lineage.csv")?;
+            writeln!(out, "item,round,monkey,worry")?;
+            (0..sim.items().unwrap_or(0))
+                .flat_map(|item| sim.lineage(item).unwrap_or_default().iter().map(move |hop| (item, hop)))
+                .try_for_each(|(item, hop)| writeln!(out, "{item},{},{},{}", hop.round, hop.monkey, hop.worry))?;
+            out.flush()
+        })
+        .map_err(|e| format!("{dir}: {e}"))?;
+    println!("Queue snapshots of {rounds} rounds exported to {dir}/snapshots.csv and {dir}/snapshots.json");
+    println!("Item lineage exported to {dir}/lineage.csv");
+
+    let loops = sim.loops().unwrap_or_default();
+    println!("{} of {} items loop", loops.len(), sim.items().unwrap_or(0));
+    loops.iter().for_each(|l| println!("  {l}"));
+    Ok(())
+}
+
+/// Run the monkeys found in `input` for a number of rounds and multiply the two highest inspection counts;
+/// worry levels are held in big integers when there is no relief to keep them down
+fn monkey_business(input: &str, relief: ReliefPolicy, rounds: usize) -> Result<u128, String> {
+    fn simulate<W: Worry>(input: &str, relief: ReliefPolicy, rounds: usize) -> Result<u128, String> {
+        Simulation::<W>::new(Monkey::parse_text(input)?, relief)?
+            .run(rounds)
+            .map(|sim| sim.monkey_business())
+    }
+    match relief {
+        ReliefPolicy::None => simulate::<BigUint>(input, relief, rounds),
+        _ => simulate::<WorryType>(input, relief, rounds)
+    }
+}
+
+/// Inspection counts after any number of rounds, skipping ahead once round states repeat;
+/// without relief, states only repeat if the monkeys' operations happen to keep worry levels bounded
+fn forecast(input: &str, relief: ReliefPolicy, rounds: u64) -> Result<Forecast, String> {
+    fn simulate<W: Worry>(input: &str, relief: ReliefPolicy, rounds: u64) -> Result<Forecast, String> {
+        Simulation::<W>::new(Monkey::parse_text(input)?, relief)?.fast_forward(rounds)
+    }
+    match relief {
+        ReliefPolicy::None => simulate::<BigUint>(input, relief, rounds),
+        _ => simulate::<WorryType>(input, relief, rounds)
+    }
 }
 
 
 type WorryType = u64;
-const WORRY_DEF: WorryType = 0;
 
-#[derive(Debug)]
-enum Operation {
-    Add(WorryType),
-    Mul(WorryType),
+/// Number type holding worry levels; comparisons are shared, arithmetic is up to the type
+trait Worry: Clone + Ord + Hash + Debug + Display {
+    fn from_int(n: WorryType) -> Self;
+    fn arith(op: BinOp, a: Self, b: Self) -> Result<Self, ExprError>;
+}
+impl Worry for WorryType {
+    fn from_int(n: WorryType) -> Self { n }
+    fn arith(op: BinOp, a: Self, b: Self) -> Result<Self, ExprError> {
+        match op {
+            BinOp::Add => a.checked_add(b).ok_or(ExprError::Overflow),
+            BinOp::Sub => a.checked_sub(b).ok_or(ExprError::Overflow),
+            BinOp::Mul => a.checked_mul(b).ok_or(ExprError::Overflow),
+            BinOp::Div => a.checked_div(b).ok_or(ExprError::DivisionByZero),
+            BinOp::Rem => a.checked_rem(b).ok_or(ExprError::DivisionByZero),
+            _ => unreachable!("{op:?} is not arithmetic")
+        }
+    }
+}
+impl Worry for BigUint {
+    fn from_int(n: WorryType) -> Self { BigUint::from(n) }
+    fn arith(op: BinOp, a: Self, b: Self) -> Result<Self, ExprError> {
+        let zero = BigUint::default();
+        match op {
+            BinOp::Add => Ok(a + b),
+            BinOp::Sub if a < b => Err(ExprError::Overflow),
+            BinOp::Sub => Ok(a - b),
+            BinOp::Mul => Ok(a * b),
+            BinOp::Div | BinOp::Rem if b == zero => Err(ExprError::DivisionByZero),
+            BinOp::Div => Ok(a / b),
+            BinOp::Rem => Ok(a % b),
+            _ => unreachable!("{op:?} is not arithmetic")
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum BinOp { Add, Sub, Mul, Div, Rem, Eq, Ne, Lt, Le, Gt, Ge }
+impl BinOp {
+    /// Longest symbols first, so that `<=` is not read as `<`
+    const SYMBOLS: [(&'static str, BinOp); 11] = [
+        ("==", BinOp::Eq), ("!=", BinOp::Ne), ("<=", BinOp::Le), (">=", BinOp::Ge), ("<", BinOp::Lt), (">", BinOp::Gt),
+        ("+", BinOp::Add), ("-", BinOp::Sub), ("*", BinOp::Mul), ("/", BinOp::Div), ("%", BinOp::Rem)
+    ];
+    fn symbol(self) -> &'static str {
+        BinOp::SYMBOLS.iter().find(|&&(_, op)| op == self).unwrap().0
+    }
+    /// Binding strength; comparisons bind loosest, then `+ -`, then `* / %`
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => 0,
+            BinOp::Add | BinOp::Sub => 1,
+            BinOp::Mul | BinOp::Div | BinOp::Rem => 2
+        }
+    }
+    fn apply<W: Worry>(self, a: W, b: W) -> Result<W, ExprError> {
+        let flag = |b: bool| Ok(W::from_int(b as WorryType));
+        match self {
+            BinOp::Eq => flag(a == b),
+            BinOp::Ne => flag(a != b),
+            BinOp::Lt => flag(a < b),
+            BinOp::Le => flag(a <= b),
+            BinOp::Gt => flag(a > b),
+            BinOp::Ge => flag(a >= b),
+            op => W::arith(op, a, b)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ExprError {
+    Unexpected { pos: usize, found: String },
+    UnexpectedEnd,
+    DivisionByZero,
+    Overflow
+}
+impl Display for ExprError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExprError::Unexpected { pos, found } => write!(f, "unexpected '{found}' at position {pos}"),
+            ExprError::UnexpectedEnd => write!(f, "expression ends too early"),
+            ExprError::DivisionByZero => write!(f, "division by zero"),
+            ExprError::Overflow => write!(f, "worry level out of range"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Token { Old, Num(WorryType), Op(BinOp), Open, Close }
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, ExprError> {
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while pos < s.len() {
+        let rest = &s[pos..];
+        let c = rest.chars().next().unwrap();
+        let (len, token) = if c.is_whitespace() {
+            pos += c.len_utf8();
+            continue
+        } else if c.is_ascii_digit() {
+            let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            (len, Token::Num(rest[..len].parse().map_err(|_| ExprError::Overflow)?))
+        } else if rest.starts_with("old") {
+            (3, Token::Old)
+        } else if c == '(' || c == ')' {
+            (1, if c == '(' { Token::Open } else { Token::Close })
+        } else {
+            let &(sym, op) = BinOp::SYMBOLS.iter()
+                .find(|(sym, _)| rest.starts_with(sym))
+                .ok_or(ExprError::Unexpected { pos, found: c.to_string() })?;
+            (sym.len(), Token::Op(op))
+        };
+        tokens.push((pos, token));
+        pos += len;
+    }
+    Ok(tokens)
 }
+
+/// Arithmetic over an item's worry level, e.g. `old * (old + 3) % 7`; comparisons give 1 when true, 0 otherwise
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Old,
+    Const(WorryType),
+    Bin(Box<Expr>, BinOp, Box<Expr>)
+}
+impl Expr {
+    fn eval<W: Worry>(&self, old: &W) -> Result<W, ExprError> {
+        match self {
+            Expr::Old => Ok(old.clone()),
+            Expr::Const(n) => Ok(W::from_int(*n)),
+            Expr::Bin(a, op, b) => op.apply(a.eval(old)?, b.eval(old)?)
+        }
+    }
+    /// Precedence climbing; operators of equal precedence associate to the left
+    fn parse(tokens: &[(usize, Token)], at: &mut usize, min: u8) -> Result<Expr, ExprError> {
+        let mut lhs = Expr::atom(tokens, at)?;
+        while let Some(&(_, Token::Op(op))) = tokens.get(*at) {
+            if op.precedence() < min { break }
+            *at += 1;
+            let rhs = Expr::parse(tokens, at, op.precedence() + 1)?;
+            lhs = Expr::Bin(Box::new(lhs), op, Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+    fn atom(tokens: &[(usize, Token)], at: &mut usize) -> Result<Expr, ExprError> {
+        let &(pos, token) = tokens.get(*at).ok_or(ExprError::UnexpectedEnd)?;
+        *at += 1;
+        match token {
+            Token::Old => Ok(Expr::Old),
+            Token::Num(n) => Ok(Expr::Const(n)),
+            Token::Open => {
+                let inner = Expr::parse(tokens, at, 0)?;
+                match tokens.get(*at) {
+                    Some((_, Token::Close)) => { *at += 1; Ok(inner) },
+                    Some(&(pos, _)) => Err(ExprError::Unexpected { pos, found: "token".to_string() }),
+                    None => Err(ExprError::UnexpectedEnd)
+                }
+            }
+            Token::Op(op) => Err(ExprError::Unexpected { pos, found: op.symbol().to_string() }),
+            Token::Close => Err(ExprError::Unexpected { pos, found: ")".to_string() })
+        }
+    }
+}
+impl FromStr for Expr {
+    type Err = ExprError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut at = 0;
+        let expr = Expr::parse(&tokens, &mut at, 0)?;
+        match tokens.get(at) {
+            None => Ok(expr),
+            Some(&(pos, _)) => Err(ExprError::Unexpected { pos, found: s[pos..].to_string() })
+        }
+    }
+}
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Old => write!(f, "old"),
+            Expr::Const(n) => write!(f, "{n}"),
+            Expr::Bin(a, op, b) => {
+                let side = |e: &Expr| match e {
+                    Expr::Bin(..) => format!("({e})"),
+                    _ => e.to_string()
+                };
+                write!(f, "{} {} {}", side(a), op.symbol(), side(b))
+            }
+        }
+    }
+}
+
+/// Decides where an item goes; `divisible by N`, or any expression that passes when nonzero
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Test {
+    Divisible(WorryType),
+    Expr(Expr)
+}
+impl Test {
+    fn passes<W: Worry>(&self, worry: &W) -> Result<bool, ExprError> {
+        let zero = W::from_int(0);
+        match self {
+            Test::Divisible(n) => W::arith(BinOp::Rem, worry.clone(), W::from_int(*n)).map(|r| r == zero),
+            Test::Expr(e) => e.eval(worry).map(|v| v != zero)
+        }
+    }
+    fn divisor(&self) -> Option<WorryType> {
+        match self {
+            Test::Divisible(n) => Some(*n),
+            Test::Expr(_) => None
+        }
+    }
+}
+impl FromStr for Test {
+    type Err = ExprError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().strip_prefix("divisible by") {
+            Some(n) => match n.trim().parse::<Expr>()?.eval(&0)? {
+                0 => Err(ExprError::DivisionByZero),
+                n => Ok(Test::Divisible(n))
+            },
+            None => s.parse().map(Test::Expr)
+        }
+    }
+}
+
 #[derive(Debug)]
-struct Monkey {
+struct Monkey<W: Worry> {
     name: usize,
-    items: VecDeque<WorryType>,
-    op: Operation,
-    test: WorryType,
+    items: VecDeque<W>,
+    op: Expr,
+    test: Test,
     send: (usize,usize),
     inspect: usize
 }
-impl Monkey {
-    fn parse_text(input: &str) -> Vec<Monkey> {
+impl<W: Worry> Monkey<W> {
+    fn parse_text(input: &str) -> Result<Vec<Monkey<W>>, String> {
         input.split("\n\n")
-            .map(|monkey| Monkey::from_str(monkey).unwrap() )
-            .fold(Vec::new(), |mut out, monkey|{
-                out.push(monkey);
-                out
-            })
+            .enumerate()
+            .map(|(i, monkey)| Monkey::from_str(monkey).map_err(|e| format!("monkey #{i}: {e}")))
+            .collect()
     }
-    fn catch(&mut self, item: WorryType) {
+    fn catch(&mut self, item: W) {
         self.items.push_back(item)
     }
-    fn throw(&self, worry: WorryType) -> (usize, WorryType) {
-        if (worry % self.test) == 0 as WorryType {
-            // Current worry level is divisible by 23.
-            // Sent to Monkey
-            (self.send.0, worry)
-        } else {
-            // Current worry level is not divisible by 23.
-            // Sent to Monkey
-            (self.send.1, worry)
-        }
+    fn throw(&self, worry: W) -> Result<(usize, W), ExprError> {
+        Ok(if self.test.passes(&worry)? { (self.send.0, worry) } else { (self.send.1, worry) })
     }
-    fn observe(&mut self, div: WorryType) -> Option<(usize, WorryType)> {
-        self.inspect += 1;
+    fn observe(&mut self, relief: &impl Fn(W) -> Result<W, ExprError>) -> Result<Option<(usize, W)>, ExprError> {
         //   Monkey inspects an item with a worry level of 79.
-        match self.items.pop_front() {
-            Some(mut worry) => {
-                //     Worry level is multiplied by 19 to 1501.
-                //     Monkey gets bored with item. Worry level is divided by 3 to 500.
-                worry %= div;
-                Some( self.throw(
-                    match self.op {
-                        Operation::Add(WORRY_DEF) => worry.add(worry),
-                        Operation::Mul(WORRY_DEF) => worry.mul(worry),
-                        Operation::Add(n) => worry + n,
-                        Operation::Mul(n) => worry * n,
-                    }
-                ))
-            }
-            None => None
-        }
+        let Some(worry) = self.items.pop_front() else { return Ok(None) };
+        self.inspect += 1;
+        //     Worry level is multiplied by 19 to 1501.
+        //     Monkey gets bored with item. Worry level is divided by 3 to 500.
+        let worry = relief(self.op.eval(&worry)?)?;
+        self.throw(worry).map(Some)
     }
-    fn observe_all(&mut self, div: WorryType) -> Vec<Option<(usize, WorryType)>> {
-        (0..self.items.len())
-            .fold(vec![], |mut out, _|{
-                out.push( self.observe(div));
-                out
-            })
+    fn observe_all(&mut self, relief: &impl Fn(W) -> Result<W, ExprError>) -> Result<Vec<(usize, W)>, ExprError> {
+        std::iter::from_fn(|| self.observe(relief).transpose())
+            .collect()
     }
     fn inspections(&self) -> usize {
         self.inspect
     }
 }
-impl Default for Monkey {
-    fn default() -> Self {
-        Monkey {
-            name: 0,
-            items: VecDeque::new(),
-            op: Operation::Add(WORRY_DEF),
-            test: WORRY_DEF,
-            send: (0,0),
+impl<W: Worry> FromStr for Monkey<W> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut name, mut items, mut op, mut test, mut if_true, mut if_false) = (None, None, None, None, None, None);
+        let target = |v: &str| v.trim()
+            .strip_prefix("throw to monkey")
+            .ok_or(format!("expected 'throw to monkey N', got '{}'", v.trim()))
+            .and_then(|n| usize::from_str(n.trim()).map_err(|e| format!("'{}': {e}", n.trim())));
+
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (key, value) = line.split_once(':').ok_or(format!("expected '<field>: <value>', got '{line}'"))?;
+            match key {
+                "Starting items" => items = Some(value.split(',')
+                    .filter(|n| !n.trim().is_empty())
+                    .map(|n| WorryType::from_str(n.trim())
+                        .map(W::from_int)
+                        .map_err(|e| format!("Starting items: '{}': {e}", n.trim())))
+                    .collect::<Result<VecDeque<_>, _>>()?),
+                "Operation" => {
+                    let expr = value.trim()
+                        .strip_prefix("new =")
+                        .ok_or(format!("Operation: expected 'new = <expression>', got '{}'", value.trim()))?;
+                    op = Some(expr.parse::<Expr>().map_err(|e| format!("Operation: {e}"))?);
+                }
+                "Test" => test = Some(value.parse::<Test>().map_err(|e| format!("Test: {e}"))?),
+                "If true" => if_true = Some(target(value).map_err(|e| format!("If true: {e}"))?),
+                "If false" => if_false = Some(target(value).map_err(|e| format!("If false: {e}"))?),
+                header => name = Some(header.strip_prefix("Monkey")
+                    .and_then(|n| usize::from_str(n.trim()).ok())
+                    .ok_or(format!("unknown field '{header}'"))?)
+            }
+        }
+
+        Ok(Monkey {
+            name: name.ok_or("missing 'Monkey N:' header")?,
+            items: items.ok_or("missing 'Starting items'")?,
+            op: op.ok_or("missing 'Operation'")?,
+            test: test.ok_or("missing 'Test'")?,
+            send: (if_true.ok_or("missing 'If true'")?, if_false.ok_or("missing 'If false'")?),
             inspect: 0
+        })
+    }
+}
+
+/// How worry levels are kept down once a monkey gets bored with an item
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ReliefPolicy {
+    /// Divided by k, rounding down; part 1 divides by 3
+    Divide(WorryType),
+    /// Reduced modulo the least common multiple of the test divisors, which leaves every test outcome unchanged
+    ModuloLcm,
+    /// Left to grow
+    None
+}
+impl ReliefPolicy {
+    /// Rounds run unless told otherwise; part 1's 20 with division or no relief at all,
+    /// as worry levels soon get out of hand without any, and part 2's 10000 with modulo relief
+    fn rounds(&self) -> usize {
+        match self {
+            ReliefPolicy::ModuloLcm => 10000,
+            ReliefPolicy::Divide(_) | ReliefPolicy::None => 20
         }
     }
 }
-impl FromStr for Monkey {
-    type Err = ();
+impl FromStr for ReliefPolicy {
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut monkey = Cell::new(Monkey::default());
-        s.lines()
-            .map(|line| line.trim().split(':').collect::<Vec<_>>())
-            .map(|parts|{
-                let m = monkey.get_mut();
-                match parts[0] {
-                    "Starting items" => {
-                        parts[1].split(',')
-                            .map(|n| WorryType::from_str(n.trim()).unwrap() )
-                            .all(|a| { m.items.push_back(a); true });
-                    }
-                    "Operation" => {
-                        let [op,act] = parts[1]
-                            .split("new = old ")
-                            .last()
-                            .unwrap()
-                            .split(' ')
-                            .collect::<Vec<_>>()[..] else { panic!("Operation: cannot be extracted") };
-                        let a = WorryType::from_str(act);
-                        match (op,a) {
-                            ("*",Ok(n)) => m.op = Operation::Mul(n),
-                            ("+",Ok(n)) => m.op = Operation::Add(n),
-                            ("*",_) => m.op = Operation::Mul(WORRY_DEF),
-                            ("+",_) => m.op = Operation::Add(WORRY_DEF),
-                            _ => {}
-                        }
-                    }
-                    "Test" => {
-                        let s = parts[1].trim().split("divisible by").last().unwrap().trim();
-                        m.test = WorryType::from_str(s).unwrap();
-                    }
-                    "If true" => {
-                        let s = parts[1].trim().split("throw to monkey").last().unwrap().trim();
-                        m.send.0 = usize::from_str(s).unwrap();
-                    }
-                    "If false" => {
-                        let s = parts[1].trim().split("throw to monkey").last().unwrap().trim();
-                        m.send.1 = usize::from_str(s).unwrap();
-                    }
-                    name => {
-                        m.name = usize::from_str(name.split(' ').last().unwrap().trim()).unwrap();
+        match s.trim() {
+            "lcm" => Ok(ReliefPolicy::ModuloLcm),
+            "none" => Ok(ReliefPolicy::None),
+            s => match s.strip_prefix("divide:").map(|k| k.trim().parse::<WorryType>()) {
+                Some(Ok(0)) => Err(format!("'{s}': cannot divide by zero")),
+                Some(Ok(k)) => Ok(ReliefPolicy::Divide(k)),
+                Some(Err(e)) => Err(format!("'{s}': {e}")),
+                None => Err(format!("'{s}': expected 'divide:<k>', 'lcm' or 'none'"))
+            }
+        }
+    }
+}
+impl Display for ReliefPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReliefPolicy::Divide(k) => write!(f, "divide:{k}"),
+            ReliefPolicy::ModuloLcm => write!(f, "lcm"),
+            ReliefPolicy::None => write!(f, "none")
+        }
+    }
+}
+
+/// An item changing hands; the round it was caught in, by whom, and its worry level on arrival
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Hop<W> {
+    round: usize,
+    monkey: usize,
+    worry: W
+}
+
+/// An item whose path goes round in circles; from hop `enters` onwards it keeps repeating the same
+/// `monkeys`, arriving at each with the same worry level, every `rounds` rounds
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ItemLoop {
+    item: usize,
+    enters: usize,
+    monkeys: Vec<usize>,
+    rounds: usize
+}
+impl Display for ItemLoop {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "item {} from hop {}: {} monkeys every {} rounds", self.item, self.enters, self.monkeys.len(), self.rounds)
+    }
+}
+
+/// Items by id, in every monkey's hands in the same order as their worry levels, together with each
+/// item's lineage and a snapshot of all the queues at the end of every round, round 0 being the start.
+/// Nothing is dropped, so memory grows with the number of items times the rounds tracked
+#[derive(Debug)]
+struct Tracking<W> {
+    queues: Vec<VecDeque<usize>>,
+    lineage: Vec<Vec<Hop<W>>>,
+    snapshots: Vec<Vec<Vec<(usize, W)>>>
+}
+
+/// Inspection counts per monkey after a number of rounds, together with the first round of the cycle
+/// of states found on the way there and its length, if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Forecast {
+    inspections: Vec<u128>,
+    period: Option<(usize, usize)>
+}
+impl Forecast {
+    fn monkey_business(&self) -> u128 {
+        top_two_product(self.inspections.iter().copied())
+    }
+}
+
+/// Product of the two highest inspection counts
+fn top_two_product(inspections: impl Iterator<Item=u128>) -> u128 {
+    let mut inspections = inspections.collect::<Vec<_>>();
+    inspections.sort_by_key(|&n| std::cmp::Reverse(n));
+    inspections.iter().take(2).product()
+}
+
+/// Monkeys taking turns in order, each throwing every item it holds before the next one goes
+struct Simulation<W: Worry> {
+    monkeys: Vec<Monkey<W>>,
+    /// Operation and operand applied to the worry level after each inspection, if any
+    relief: Option<(BinOp, W)>,
+    rounds: usize,
+    tracking: Option<Tracking<W>>
+}
+impl<W: Worry> Simulation<W> {
+    fn new(monkeys: Vec<Monkey<W>>, policy: ReliefPolicy) -> Result<Simulation<W>, String> {
+        if let Some(m) = monkeys.iter().enumerate().find(|&(i, m)| m.name != i).map(|(_, m)| m) {
+            return Err(format!("monkey {} is out of order", m.name))
+        }
+        if let Some(m) = monkeys.iter().find(|m| m.send.0.max(m.send.1) >= monkeys.len()) {
+            return Err(format!("monkey {} throws to a monkey that does not exist", m.name))
+        }
+        let relief = match policy {
+            ReliefPolicy::Divide(k) => Some((BinOp::Div, W::from_int(k))),
+            ReliefPolicy::ModuloLcm => Some((BinOp::Rem, W::from_int(
+                monkeys.iter()
+                    .map(|m| m.test.divisor())
+                    .try_fold(1, |lcm: WorryType, d| d.map(|d| lcm.lcm(&d)))
+                    .ok_or("modulo relief needs every test to be a divisibility test")?
+            ))),
+            ReliefPolicy::None => None
+        };
+        Ok(Simulation { monkeys, relief, rounds: 0, tracking: None })
+    }
+    /// Follow every item from now on; items are numbered monkey by monkey in the order they are held
+    fn tracking(mut self) -> Simulation<W> {
+        let mut id = 0..;
+        let queues = self.monkeys.iter()
+            .map(|m| id.by_ref().take(m.items.len()).collect::<VecDeque<_>>())
+            .collect::<Vec<_>>();
+        let lineage = self.monkeys.iter()
+            .flat_map(|m| m.items.iter().map(|w| vec![Hop { round: self.rounds, monkey: m.name, worry: w.clone() }]))
+            .collect();
+        self.tracking = Some(Tracking { queues, lineage, snapshots: vec![] });
+        self.snapshot();
+        self
+    }
+    fn snapshot(&mut self) {
+        if let Some(tracking) = self.tracking.as_mut() {
+            tracking.snapshots.push(
+                self.monkeys.iter()
+                    .zip(&tracking.queues)
+                    .map(|(m, ids)| ids.iter().copied().zip(m.items.iter().cloned()).collect())
+                    .collect()
+            );
+        }
+    }
+    fn round(&mut self) -> Result<(), String> {
+        let relief = |worry: W| match &self.relief {
+            Some((op, operand)) => op.apply(worry, operand.clone()),
+            None => Ok(worry)
+        };
+        for i in 0..self.monkeys.len() {
+            self.monkeys[i].observe_all(&relief)
+                .map_err(|e| format!("round {}, monkey {i}: {e}", self.rounds + 1))?
+                .into_iter()
+                .for_each(|(monkey, item)| {
+                    if let Some(tracking) = self.tracking.as_mut() {
+                        let id = tracking.queues[i].pop_front().expect("Ops! item ids out of step with items");
+                        tracking.queues[monkey].push_back(id);
+                        tracking.lineage[id].push(Hop { round: self.rounds + 1, monkey, worry: item.clone() });
                     }
-                }
-                true
+                    self.monkeys[monkey].catch(item)
+                })
+        }
+        self.rounds += 1;
+        self.snapshot();
+        Ok(())
+    }
+    fn run(mut self, rounds: usize) -> Result<Simulation<W>, String> {
+        (0..rounds).try_for_each(|_| self.round())?;
+        Ok(self)
+    }
+    fn monkey_business(&self) -> u128 {
+        top_two_product(self.monkeys.iter().map(|m| m.inspections() as u128))
+    }
+    /// Inspection counts after `rounds` more rounds, without running them all; every state at the end of a round
+    /// is remembered until one comes round again, from then on each period adds the same inspections.
+    /// Leaves the simulation where the repeat was found
+    fn fast_forward(mut self, rounds: u64) -> Result<Forecast, String> {
+        let mut seen = HashMap::new();
+        let mut history: Vec<Vec<usize>> = vec![];
+        loop {
+            let done = history.len();
+            history.push(self.monkeys.iter().map(Monkey::inspections).collect());
+            if done as u64 == rounds {
+                return Ok(Forecast { inspections: history[done].iter().map(|&n| n as u128).collect(), period: None })
+            }
+            let state = self.monkeys.iter().map(|m| m.items.clone()).collect::<Vec<_>>();
+            if let Some(first) = seen.insert(state, done) {
+                let period = done - first;
+                let remaining = rounds - done as u64;
+                let (cycles, rest) = ((remaining / period as u64) as u128, (remaining % period as u64) as usize);
+                let gained = |from: usize, to: usize, m: usize| (history[to][m] - history[from][m]) as u128;
+                return Ok(Forecast {
+                    inspections: (0..self.monkeys.len())
+                        .map(|m| history[done][m] as u128 + cycles * gained(first, done, m) + gained(first, first + rest, m))
+                        .collect(),
+                    period: Some((self.rounds - period, period))
+                })
+            }
+            self.round()?;
+        }
+    }
+    /// Number of items tracked
+    fn items(&self) -> Option<usize> {
+        self.tracking.as_ref().map(|t| t.lineage.len())
+    }
+    /// Every monkey that held the item, starting with the one holding it when tracking began
+    fn lineage(&self, item: usize) -> Option<&[Hop<W>]> {
+        self.tracking.as_ref().and_then(|t| t.lineage.get(item)).map(Vec::as_slice)
+    }
+    /// Items that arrived at the same monkey with the same worry level twice; from then on their path repeats
+    fn loops(&self) -> Option<Vec<ItemLoop>> {
+        let tracking = self.tracking.as_ref()?;
+        Some(tracking.lineage.iter()
+            .enumerate()
+            .filter_map(|(item, hops)| {
+                let mut seen = HashMap::new();
+                hops.iter()
+                    .enumerate()
+                    .find_map(|(i, hop)| seen.insert((hop.monkey, &hop.worry), i).map(|enters| (enters, i)))
+                    .map(|(enters, again)| ItemLoop {
+                        item,
+                        enters,
+                        monkeys: hops[enters..again].iter().map(|h| h.monkey).collect(),
+                        rounds: hops[again].round - hops[enters].round
+                    })
             })
-            .all(|run| run);
+            .collect())
+    }
+    fn tracked(&self) -> std::io::Result<&Tracking<W>> {
+        self.tracking.as_ref().ok_or(std::io::Error::other("items are not being tracked"))
+    }
+    /// `round,monkey,position,item,worry` rows, one per item held at the end of each round
+    fn write_snapshots_csv(&self, mut out: impl Write) -> std::io::Result<()> {
+        writeln!(out, "round,monkey,position,item,worry")?;
+        for (round, monkeys) in self.tracked()?.snapshots.iter().enumerate() {
+            for (monkey, items) in monkeys.iter().enumerate() {
+                for (pos, (item, worry)) in items.iter().enumerate() {
+                    writeln!(out, "{round},{monkey},{pos},{item},{worry}")?;
+                }
+            }
+        }
+        out.flush()
+    }
+    /// `[{"round":0,"monkeys":[[{"item":0,"worry":79},...],...]},...]`
+    fn write_snapshots_json(&self, mut out: impl Write) -> std::io::Result<()> {
+        let sep = |out: &mut dyn Write, i: usize| if i > 0 { write!(out, ",") } else { Ok(()) };
+        write!(out, "[")?;
+        for (round, monkeys) in self.tracked()?.snapshots.iter().enumerate() {
+            sep(&mut out, round)?;
+            write!(out, "{{\"round\":{round},\"monkeys\":[")?;
+            for (monkey, items) in monkeys.iter().enumerate() {
+                sep(&mut out, monkey)?;
+                write!(out, "[")?;
+                for (pos, (item, worry)) in items.iter().enumerate() {
+                    sep(&mut out, pos)?;
+                    write!(out, "{{\"item\":{item},\"worry\":{worry}}}")?;
+                }
+                write!(out, "]")?;
+            }
+            write!(out, "]}}")?;
+        }
+        write!(out, "]")?;
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAMPLE: &str = "Monkey 0:\n  Starting items: 79, 98\n  Operation: new = old * 19\n  Test: divisible by 23\n    If true: throw to monkey 2\n    If false: throw to monkey 3\n\n\
+        Monkey 1:\n  Starting items: 54, 65, 75, 74\n  Operation: new = old + 6\n  Test: divisible by 19\n    If true: throw to monkey 2\n    If false: throw to monkey 0\n\n\
+        Monkey 2:\n  Starting items: 79, 60, 97\n  Operation: new = old * old\n  Test: divisible by 13\n    If true: throw to monkey 1\n    If false: throw to monkey 3\n\n\
+        Monkey 3:\n  Starting items: 74\n  Operation: new = old + 3\n  Test: divisible by 17\n    If true: throw to monkey 0\n    If false: throw to monkey 1";
+
+    #[test]
+    fn test_expressions() {
+        let eval = |s: &str, old: WorryType| s.parse::<Expr>().and_then(|e| e.eval(&old));
+        assert_eq!(eval("old * 0", 7), Ok(0));
+        assert_eq!(eval("old + 0", 7), Ok(7));
+        assert_eq!(eval("old * old", 7), Ok(49));
+        assert_eq!(eval("2 + old * 3 - 1", 5), Ok(16));
+        assert_eq!(eval("(2 + old) * 3 % 4", 5), Ok(1));
+        assert_eq!(eval("100 / old / 2", 5), Ok(10));
+        assert_eq!(eval("old % 3 == 1", 7), Ok(1));
+        assert_eq!(eval("old / 0", 7), Err(ExprError::DivisionByZero));
+        assert_eq!(eval("old - 8", 7), Err(ExprError::Overflow));
+        assert_eq!(eval("old * (2", 7), Err(ExprError::UnexpectedEnd));
+        assert_eq!(eval("old ^ 2", 7), Err(ExprError::Unexpected { pos: 4, found: "^".to_string() }));
+        assert_eq!(eval("old 2", 7), Err(ExprError::Unexpected { pos: 4, found: "2".to_string() }));
+        assert_eq!("(old + 1) * (old % 3)".parse::<Expr>().unwrap().to_string(), "(old + 1) * (old % 3)");
+    }
+    #[test]
+    fn test_monkey_rules() {
+        let monkey = Monkey::<WorryType>::from_str(
+            "Monkey 3:\n  Starting items: 74\n  Operation: new = old * 0\n  Test: old < 10\n    If true: throw to monkey 1\n    If false: throw to monkey 2"
+        ).unwrap();
+        assert_eq!(monkey.op, Expr::Bin(Box::new(Expr::Old), BinOp::Mul, Box::new(Expr::Const(0))));
+        assert_eq!(monkey.test.divisor(), None);
+        let mut monkey = monkey;
+        assert_eq!(monkey.observe_all(&Ok), Ok(vec![(1, 0)]));
+        assert_eq!(monkey.inspections(), 1);
+
+        assert!(Monkey::<WorryType>::from_str("Monkey 0:\n  Starting items: 1\n  Operation: new = old ** 2").is_err());
+        assert!(Monkey::<WorryType>::from_str("Monkey 0:\n  Starting items: 1\n  Operation: new = old\n  Test: divisible by 0").is_err());
+        assert_eq!(
+            Monkey::<WorryType>::from_str("Monkey 0:\n  Starting items: 1\n  Operation: new = old + 1\n  Test: divisible by 2\n  If true: throw to monkey 1").map(|_| ()),
+            Err("missing 'If false'".to_string())
+        );
+    }
+    #[test]
+    fn test_relief_policies() {
+        assert_eq!(monkey_business(SAMPLE, ReliefPolicy::Divide(3), 20), Ok(10605));
+        assert_eq!(monkey_business(SAMPLE, ReliefPolicy::ModuloLcm, 10000), Ok(2713310158));
+        // without relief worry levels outgrow u64 quickly, big integers agree with modulo relief
+        assert!(Simulation::<WorryType>::new(Monkey::parse_text(SAMPLE).unwrap(), ReliefPolicy::None).unwrap().run(20).is_err());
+        assert_eq!(monkey_business(SAMPLE, ReliefPolicy::None, 20), monkey_business(SAMPLE, ReliefPolicy::ModuloLcm, 20));
+
+        assert_eq!("divide:3".parse(), Ok(ReliefPolicy::Divide(3)));
+        assert!("divide:0".parse::<ReliefPolicy>().is_err());
+        assert_eq!(ReliefPolicy::ModuloLcm.to_string().parse(), Ok(ReliefPolicy::ModuloLcm));
+        assert_eq!(ReliefPolicy::None.rounds(), 20);
+
+        // fast-forwarding without relief goes through big integers as well
+        assert_eq!(
+            forecast(SAMPLE, ReliefPolicy::None, 20).map(|f| f.monkey_business()),
+            monkey_business(SAMPLE, ReliefPolicy::None, 20)
+        );
+    }
+    #[test]
+    fn test_tracking() {
+        let sim = Simulation::<WorryType>::new(Monkey::parse_text(SAMPLE).unwrap(), ReliefPolicy::Divide(3)).unwrap()
+            .tracking()
+            .run(1)
+            .unwrap();
+        assert_eq!(sim.items(), Some(10));
+        assert_eq!(sim.lineage(0), Some([
+            Hop { round: 0, monkey: 0, worry: 79 },
+            Hop { round: 1, monkey: 3, worry: 500 },
+            Hop { round: 1, monkey: 1, worry: 167 }
+        ].as_slice()));
+        let (mut csv, mut json) = (vec![], vec![]);
+        sim.write_snapshots_csv(&mut csv).unwrap();
+        sim.write_snapshots_json(&mut json).unwrap();
+        let (csv, json) = (String::from_utf8(csv).unwrap(), String::from_utf8(json).unwrap());
+        assert!(csv.starts_with("round,monkey,position,item,worry\n0,0,0,0,79\n"));
+        assert!(csv.contains("\n1,1,2,0,167\n1,1,3,1,207\n") && csv.ends_with("\n1,1,5,8,1046\n"));
+        assert!(json.ends_with("[],[]]}]"));
+        assert!(json.starts_with("[{\"round\":0,\"monkeys\":[[{\"item\":0,\"worry\":79},{\"item\":1,\"worry\":98}],"));
 
-        Ok(monkey.take())
+        let sim = Simulation::<WorryType>::new(Monkey::parse_text(SAMPLE).unwrap(), ReliefPolicy::ModuloLcm).unwrap()
+            .tracking()
+            .run(500)
+            .unwrap();
+        let loops = sim.loops().unwrap();
+        assert!(!loops.is_empty());
+        loops.iter()
+            .for_each(|l| {
+                let hops = sim.lineage(l.item).unwrap();
+                let (from, to) = (&hops[l.enters], &hops[l.enters + l.monkeys.len()]);
+                assert_eq!((from.monkey, &from.worry), (to.monkey, &to.worry));
+                assert_eq!(to.round - from.round, l.rounds);
+            });
+        assert_eq!(Simulation::<WorryType>::new(vec![], ReliefPolicy::None).unwrap().loops(), None);
+        assert!(Simulation::<WorryType>::new(vec![], ReliefPolicy::None).unwrap().write_snapshots_csv(vec![]).is_err());
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_fast_forward() {
+        let sim = |relief| Simulation::<WorryType>::new(Monkey::parse_text(SAMPLE).unwrap(), relief).unwrap();
+
+        let forecast = sim(ReliefPolicy::ModuloLcm).fast_forward(10000).unwrap();
+        assert_eq!(forecast.period, None);
+        assert_eq!(forecast.monkey_business(), 2713310158);
+        [1, 20, 1000, 4321].into_iter()
+            .for_each(|rounds| assert_eq!(
+                sim(ReliefPolicy::ModuloLcm).fast_forward(rounds).unwrap().monkey_business(),
+                sim(ReliefPolicy::ModuloLcm).run(rounds as usize).unwrap().monkey_business()
+            ));
+        assert_eq!(sim(ReliefPolicy::Divide(3)).fast_forward(20).unwrap().monkey_business(), 10605);
+
+        let target = 1_000_000_000_000;
+        let forecast = sim(ReliefPolicy::ModuloLcm).fast_forward(target).unwrap();
+        let (start, length) = forecast.period.unwrap();
+        let inspections = |rounds: u64| sim(ReliefPolicy::ModuloLcm).run(rounds as usize).unwrap()
+            .monkeys.iter().map(|m| m.inspections() as u128).collect::<Vec<_>>();
+        let (start, length) = (start as u64, length as u64);
+        let (first, cycle, rest) = (inspections(start), inspections(start + length), inspections(start + (target - start) % length));
+        assert_eq!(forecast.inspections, (0..4)
+            .map(|m| rest[m] + (target - start) as u128 / length as u128 * (cycle[m] - first[m]))
+            .collect::<Vec<_>>());
+    }
+}
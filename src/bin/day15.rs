@@ -2,6 +2,7 @@ use std::collections::HashSet;
 use std::fmt::{Debug, Formatter};
 use std::ops::RangeInclusive;
 use std::str::FromStr;
+use advent2022::interval::IntervalSet;
 
 // const INPUT : &str = "Sensor at x=2, y=18: closest beacon is at x=-2, y=15
 // Sensor at x=9, y=16: closest beacon is at x=10, y=16
@@ -35,14 +36,12 @@ fn main() {
     println!("{}-{}={} (4793062)", positions,beacons.len(),positions-beacons.len());
 
     // Component 2
-    let (line, v) = (0..=4000000)
-        .map(|line| (line,area.sensor_coverage_at(line)))
-        .filter(|(_,v)| v.len() > 1 )
-        .filter(|(_,v)| v[1].start() - v[0].end() > 1 )
-        .next().unwrap();
+    let (line, gap) = (0..=4000000)
+        .find_map(|line| area.sensor_coverage_at(line).gaps().next().map(|gap| (line,gap)))
+        .unwrap();
 
-    let total = (v[0].end() + 1) * 4000000 + line;
-    println!("Signal Coverage @{line} = {:?} \nFreq of distress beacon: {total}", v);
+    let total = gap.start() * 4000000 + line;
+    println!("Signal Coverage gap @{line} = {:?} \nFreq of distress beacon: {total}", gap);
 }
 
 struct Area {
@@ -72,32 +71,10 @@ impl Area {
     fn beacons_at(&self, line:isize) -> HashSet<Coord> {
         self.sensors.iter().filter_map(|s| if s.beacon.y == line { Some(s.beacon)} else {None}).collect::<HashSet<_>>()
     }
-    fn sensor_coverage_at(&self, line: isize) -> Vec<RangeInclusive<isize>> {
-
-        let mut result = vec![];
-
-        let mut ranges = self.sensors.iter()
-                .filter_map(|sensor| sensor.coverage_at(line))
-                .collect::<Vec<_>>();
-
-        ranges.sort_by_key(|a| *a.start());
-
-        if let Some(last) = ranges.into_iter()
-            .reduce(|a, b|
-                if a.end() >= &(b.start()-1) {
-                    if a.end() < b.end() {
-                        *a.start()..=*b.end()
-                    } else { a }
-                } else {
-                    // We got a range gap here hence we must save range A
-                    // while we pass on Range B to the next iteration
-                    result.push(a);
-                    b
-                }
-            ) {
-            result.push(last);
-        }
-        result
+    fn sensor_coverage_at(&self, line: isize) -> IntervalSet<isize> {
+        self.sensors.iter()
+            .filter_map(|sensor| sensor.coverage_at(line))
+            .collect::<IntervalSet<_>>()
     }
 }
 
@@ -1,124 +1,305 @@
-use std::iter::once;
-
-#[derive(Debug,Copy,Clone,PartialEq)]
-enum Move { Rock=1, Paper, Scissors }
-impl From<u8> for Move {
-    fn from(c: u8) -> Self {
-        match c {
-            b'A' | b'X' => Move::Rock,
-            b'B' | b'Y' => Move::Paper,
-            b'C' | b'Z' => Move::Scissors,
-            _ => unreachable!()
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// A move is an index into the move set held by the `Rules`
+#[derive(Debug,Copy,Clone,PartialEq,Eq,Hash)]
+struct Move(usize);
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+enum Outcome { Loss, Draw, Win }
+impl Outcome {
+    fn index(&self) -> usize {
+        match self {
+            Outcome::Loss => 0,
+            Outcome::Draw => 1,
+            Outcome::Win => 2
         }
     }
 }
-impl Move {
-    fn is_winning(&self, other:&Self) -> bool {
-        matches!(
-            (other,self),
-            (Move::Rock, Move::Paper) |
-            (Move::Paper, Move::Scissors) |
-            (Move::Scissors, Move::Rock)
-        )
+
+/// Rules of the game; the move set, the beats-relation and the scoring tables
+/// ```text
+/// Rock 1 > Scissors
+/// Paper 2 > Rock
+/// Scissors 3 > Paper
+/// outcome 0 3 6
+/// ```
+#[derive(Debug,Clone)]
+struct Rules {
+    names: Vec<String>,
+    beats: Vec<Vec<bool>>,
+    move_score: Vec<u64>,
+    outcome_score: [u64;3]
+}
+impl Rules {
+    fn classic() -> Rules {
+        Rules::from_str(
+            "Rock 1 > Scissors\n\
+            Paper 2 > Rock\n\
+            Scissors 3 > Paper\n\
+            outcome 0 3 6"
+        ).expect("classic rules are well formed")
+    }
+    fn lizard_spock() -> Rules {
+        Rules::from_str(
+            "Rock 1 > Scissors Lizard\n\
+            Paper 2 > Rock Spock\n\
+            Scissors 3 > Paper Lizard\n\
+            Lizard 4 > Paper Spock\n\
+            Spock 5 > Rock Scissors\n\
+            outcome 0 3 6"
+        ).expect("lizard spock rules are well formed")
     }
-    fn outcome(&self, other:&Self) -> Outcome {
-        if self.is_winning(other) {
+    fn len(&self) -> usize {
+        self.names.len()
+    }
+    fn moves(&self) -> impl Iterator<Item=Move> {
+        (0..self.len()).map(Move)
+    }
+    fn name(&self, m: Move) -> &str {
+        self.names[m.0].as_str()
+    }
+    fn is_winning(&self, me: Move, other: Move) -> bool {
+        self.beats[me.0][other.0]
+    }
+    fn outcome(&self, me: Move, other: Move) -> Outcome {
+        if self.is_winning(me, other) {
             Outcome::Win
-        } else if other.is_winning(self) {
+        } else if self.is_winning(other, me) {
             Outcome::Loss
         } else {
             Outcome::Draw
         }
     }
-    fn derive(&self, out:Outcome) -> Move {
-        let iter = once(Move::Rock).chain(once(Move::Paper)).chain(once(Move::Scissors)).cycle();
-        // match out {
-        //     Outcome::Draw => iter.skip_while(|e| self != e).skip(0).next(),
-        //     Outcome::Win => iter.skip_while(|e| self != e).skip(1).next()
-        //     Outcome::Loss => iter.skip_while(|e| self != e).skip(2).next(),
-        // }.unwrap()
-        iter.skip_while(|e| self != e).skip(out as usize).next().unwrap()
+    /// Find the move that produces the requested outcome against the other move
+    fn derive(&self, other: Move, out: Outcome) -> Option<Move> {
+        self.moves().find(|&m| self.outcome(m, other) == out)
     }
-}
-#[derive(Debug,Copy,Clone)]
-enum Outcome { Draw, Win, Loss }
-impl From<Move> for Outcome {
-    fn from(m: Move) -> Self {
-        match m {
-            Move::Rock => Outcome::Loss,
-            Move::Paper => Outcome::Draw,
-            Move::Scissors => Outcome::Win
-        }
+    fn score(&self, round: (Move, Move)) -> u64 {
+        let (other, me) = round;
+        self.outcome_score[self.outcome(me, other).index()] + self.move_score[me.0]
     }
 }
-impl Outcome {
-    fn score_value(&self) -> u64 {
-        match self {
-            Outcome::Loss => 0,
-            Outcome::Draw => 3,
-            Outcome::Win => 6
+impl FromStr for Rules {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut outcome_score = [0, 3, 6];
+        let mut defs = vec![];
+
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Some("outcome") => {
+                    let scores = parts
+                        .map(|n| u64::from_str(n).map_err(|e| format!("outcome: {e}")))
+                        .collect::<Result<Vec<_>,_>>()?;
+                    let [l, d, w] = scores[..] else { return Err(format!("outcome: expected 3 scores, got {}", scores.len())) };
+                    outcome_score = [l, d, w];
+                }
+                Some(name) => {
+                    let score = parts.next()
+                        .ok_or(format!("{name}: missing score"))
+                        .and_then(|n| u64::from_str(n).map_err(|e| format!("{name}: {e}")))?;
+                    let beaten = match parts.next() {
+                        Some(">") => parts.map(String::from).collect::<Vec<_>>(),
+                        None => vec![],
+                        Some(t) => return Err(format!("{name}: expected '>', got '{t}'"))
+                    };
+                    defs.push((name.to_string(), score, beaten));
+                }
+                None => unreachable!()
+            }
         }
+
+        let names = defs.iter().map(|(n, ..)| n.clone()).collect::<Vec<_>>();
+        let index = names.iter().enumerate().map(|(i, n)| (n.as_str(), i)).collect::<HashMap<_,_>>();
+        if index.len() != names.len() {
+            return Err("duplicate move names".to_string());
+        }
+        let mut beats = vec![vec![false; names.len()]; names.len()];
+        for (i, (name, _, beaten)) in defs.iter().enumerate() {
+            for b in beaten {
+                let &j = index.get(b.as_str()).ok_or(format!("{name}: unknown move '{b}'"))?;
+                if i == j || beats[j][i] {
+                    return Err(format!("{name}: conflicting rule against '{b}'"));
+                }
+                beats[i][j] = true;
+            }
+        }
+
+        Ok(Rules {
+            move_score: defs.iter().map(|&(_, s, _)| s).collect(),
+            names,
+            beats,
+            outcome_score
+        })
     }
 }
+
+/// A round as read from the strategy guide; the opponent's move and our column symbol
 #[derive(Debug,Copy,Clone)]
-struct Round(Move,Move);
+struct Round {
+    other: Move,
+    column: usize
+}
 impl Round {
-    fn new(round:&str) -> Round {
-        if let &[a,_,b] = round.as_bytes() { Round(Move::from(a), Move::from(b)) } else { unreachable!() }
+    fn parse(round: &str) -> Result<Round, String> {
+        if let &[a, b' ', b] = round.as_bytes() {
+            match (a, b) {
+                (b'A'..=b'W', b'X'..=b'Z') => Ok(Round { other: Move((a - b'A') as usize), column: (b - b'X') as usize }),
+                _ => Err(format!("invalid round '{round}'"))
+            }
+        } else {
+            Err(format!("invalid round '{round}'"))
+        }
+    }
+    fn parse_guide(input: &str) -> Result<Vec<Round>, String> {
+        input.lines().map(Round::parse).collect()
+    }
+}
+
+/// Decodes our column symbol into a move, given the rules and the opponent's move
+trait Strategy {
+    fn decode(&self, rules: &Rules, other: Move, column: usize) -> Option<Move>;
+}
+impl<F> Strategy for F where F: Fn(&Rules, Move, usize) -> Option<Move> {
+    fn decode(&self, rules: &Rules, other: Move, column: usize) -> Option<Move> {
+        self(rules, other, column)
+    }
+}
+/// Column symbol denotes our move; X, Y, Z, ... map onto the moves in order
+struct AsMove;
+impl Strategy for AsMove {
+    fn decode(&self, rules: &Rules, _: Move, column: usize) -> Option<Move> {
+        (column < rules.len()).then_some(Move(column))
+    }
+}
+/// Column symbol denotes the outcome we need; X lose, Y draw, Z win
+struct AsOutcome;
+impl Strategy for AsOutcome {
+    fn decode(&self, rules: &Rules, other: Move, column: usize) -> Option<Move> {
+        let out = match column {
+            0 => Outcome::Loss,
+            1 => Outcome::Draw,
+            2 => Outcome::Win,
+            _ => return None
+        };
+        rules.derive(other, out)
+    }
+}
+/// Column symbol denotes our move through an arbitrary mapping
+#[derive(Debug,Clone,PartialEq)]
+struct Mapping(Vec<Move>);
+impl Strategy for Mapping {
+    fn decode(&self, _: &Rules, _: Move, column: usize) -> Option<Move> {
+        self.0.get(column).copied()
+    }
+}
+
+struct Game<'a> {
+    rules: &'a Rules,
+    rounds: &'a [Round]
+}
+impl Game<'_> {
+    fn new<'a>(rules: &'a Rules, rounds: &'a [Round]) -> Game<'a> {
+        Game { rules, rounds }
     }
-    fn derived(round:&str) -> Round {
-        let Round(a,b) = Round::new(round);
-        Round(a, a.derive(Outcome::from(b)))
+    fn score(&self, strategy: &impl Strategy) -> Result<u64, String> {
+        self.rounds.iter()
+            .map(|r| {
+                if r.other.0 >= self.rules.len() {
+                    return Err(format!("opponent move {} is not in the move set", r.other.0));
+                }
+                strategy.decode(self.rules, r.other, r.column)
+                    .map(|me| self.rules.score((r.other, me)))
+                    .ok_or(format!("cannot decode column {} against {}", r.column, self.rules.name(r.other)))
+            })
+            .sum()
     }
-    fn score(&self) -> u64 {
-        let Round(other, me) = self;
-        me.outcome(other).score_value() + *me as u64
+    /// Brute force all column-to-move assignments and return the highest scoring one
+    fn best_mapping(&self) -> Option<(Mapping, u64)> {
+        let columns = self.rounds.iter().map(|r| r.column + 1).max().unwrap_or(0);
+        let mut best = None;
+        permutations(self.rules.len(), columns, &mut vec![], &mut |perm| {
+            let mapping = Mapping(perm.to_vec());
+            if let Ok(score) = self.score(&mapping) {
+                if best.as_ref().is_none_or(|(_, max)| score > *max) {
+                    best = Some((mapping, score));
+                }
+            }
+        });
+        best
+    }
+}
+
+/// Visit every ordered selection of `k` distinct moves out of `n`
+fn permutations(n: usize, k: usize, current: &mut Vec<Move>, visit: &mut impl FnMut(&[Move])) {
+    if current.len() == k {
+        visit(current);
+        return
+    }
+    for m in (0..n).map(Move) {
+        if !current.contains(&m) {
+            current.push(m);
+            permutations(n, k, current, visit);
+            current.pop();
+        }
     }
 }
 
 fn main() {
-    let (score1, score2) = std::fs::read_to_string("./src/bin/day2_input.txt")
-        .unwrap()
-        .lines()
-        .map(|round| (
-            Round::new(round).score(),
-            Round::derived(round).score()
-        ))
-        .reduce(|sum, round| {
-            (sum.0 + round.0, sum.1 + round.1)
-        })
-        .unwrap_or_else(|| panic!("Empty iterator ?"));
-    println!("Strategy 1 : 15632 {:?}",score1);
-    println!("Strategy 2 : 14416 {:?}",score2);
-}
-
-// fn strategy_1(round:&str) -> u64 {
-//     match round {
-//         // Question 1: ABC, XYZ denotes player choices
-//         "A X" => 3+1,
-//         "A Y" => 6+2,
-//         "A Z" => 0+3,
-//         "B X" => 0+1,
-//         "B Y" => 3+2,
-//         "B Z" => 6+3,
-//         "C X" => 6+1,
-//         "C Y" => 0+2,
-//         "C Z" => 3+3,
-//         _ => panic!("unknown input")
-//     }
-// }
-// fn strategy_2(round:&str) -> u64 {
-//     match round {
-//         // Question 2: XYZ denotes your choice results to loose, draw, win
-//         "A X" => 0+3,
-//         "A Y" => 3+1,
-//         "A Z" => 6+2,
-//         "B X" => 0+1,
-//         "B Y" => 3+2,
-//         "B Z" => 6+3,
-//         "C X" => 0+2,
-//         "C Y" => 3+3,
-//         "C Z" => 6+1,
-//         _ => panic!("unknown input")
-//     }
-// }
+    let input = std::fs::read_to_string("./src/bin/day2_input.txt").expect("Ops!");
+    let rounds = Round::parse_guide(input.as_str()).unwrap_or_else(|e| panic!("{e}"));
+
+    let rules = Rules::classic();
+    let game = Game::new(&rules, &rounds);
+
+    println!("Strategy 1 : 15632 {:?}", game.score(&AsMove));
+    println!("Strategy 2 : 14416 {:?}", game.score(&AsOutcome));
+
+    let (mapping, score) = game.best_mapping().expect("Ops! no valid decoding");
+    println!("Best decoding : {:?} => {score}",
+             mapping.0.iter().map(|&m| rules.name(m)).collect::<Vec<_>>()
+    );
+
+    let rules = Rules::lizard_spock();
+    let game = Game::new(&rules, &rounds);
+    println!("Rock-Paper-Scissors-Lizard-Spock, Strategy 2 : {:?}", game.score(&AsOutcome));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const INPUT: &str = "A Y\nB X\nC Z";
+
+    #[test]
+    fn test_classic_strategies() {
+        let rules = Rules::classic();
+        let rounds = Round::parse_guide(INPUT).unwrap();
+        let game = Game::new(&rules, &rounds);
+        assert_eq!(game.score(&AsMove), Ok(15));
+        assert_eq!(game.score(&AsOutcome), Ok(12));
+        assert_eq!(game.score(&|_: &Rules, other: Move, _| Some(other)), Ok(15));
+    }
+    #[test]
+    fn test_best_mapping() {
+        let rules = Rules::classic();
+        let rounds = Round::parse_guide(INPUT).unwrap();
+        let (mapping, score) = Game::new(&rules, &rounds).best_mapping().unwrap();
+        assert_eq!(score, 24);
+        assert_eq!(mapping, Mapping(vec![Move(2), Move(1), Move(0)]));
+    }
+    #[test]
+    fn test_lizard_spock() {
+        let rules = Rules::lizard_spock();
+        assert_eq!(rules.len(), 5);
+        rules.moves().for_each(|m| {
+            assert_eq!(rules.moves().filter(|&o| rules.is_winning(m, o)).count(), 2);
+        });
+        assert_eq!(rules.derive(Move(4), Outcome::Win), Some(Move(1)));
+        assert!(Rules::from_str("Rock 1 > Paper\nPaper 2 > Rock").is_err());
+        assert!(Rules::from_str("Rock 1 > Stone").is_err());
+    }
+}
@@ -1,20 +1,5 @@
-use std::ops::RangeInclusive;
 use std::str::FromStr;
-
-trait InclusiveRangeExt {
-    fn is_subset(&self, other: &Self) -> bool;
-    fn is_overlapping(&self, other: &Self) -> bool;
-}
-
-impl<T> InclusiveRangeExt for RangeInclusive<T>
-    where T : PartialOrd {
-    fn is_subset(&self, other: &Self) -> bool {
-        self.contains(other.start()) && self.contains(other.end())
-    }
-    fn is_overlapping(&self, other: &Self) -> bool {
-        self.contains(other.start()) || self.contains(other.end())
-    }
-}
+use advent2022::interval::Interval;
 
 fn main() {
 
@@ -33,14 +18,14 @@ fn main() {
 
     let out = pairs.iter()
         .filter(|(a,b)|
-            a.is_subset(b) || b.is_subset(a)
+            a.contains_range(b) || b.contains_range(a)
         )
         .count();
     println!("Component 1 = {out}");
 
     let out = pairs.iter()
         .filter(|(a,b)|
-            a.is_overlapping(b) || b.is_overlapping(a)
+            a.overlaps(b)
         )
         .count();
     println!("Component 2 = {out}");
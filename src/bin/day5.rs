@@ -1,106 +1,327 @@
-use std::collections::HashMap;
-use std::num::ParseIntError;
+use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
-#[derive(Debug,Copy,Clone)]
+/// Moves `count` crates between the stacks labelled `from` and `to`
+#[derive(Debug,Clone,PartialEq)]
 struct Move {
     count: usize,
-    from: usize,
-    to: usize
+    from: String,
+    to: String
 }
 impl FromStr for Move {
-    type Err = ParseIntError;
+    type Err = MoveError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let [_,count,_,from,_,to] = s.split(' ').collect::<Vec<_>>()[..] {
-            Ok(
+        match s.split(' ').collect::<Vec<_>>()[..] {
+            ["move", count, "from", from, "to", to] => Ok(
                 Move {
-                    count: usize::from_str(count)?,
-                    from: usize::from_str(from)?,
-                    to: usize::from_str(to)?,
+                    count: usize::from_str(count).map_err(|_| MoveError::Parse(s.to_string()))?,
+                    from: from.to_string(),
+                    to: to.to_string(),
                 }
-            )
-        } else {
-            unreachable!()
+            ),
+            _ => Err(MoveError::Parse(s.to_string()))
         }
     }
 }
 impl Move {
-    fn parse_moves(moves:&str) -> Vec<Move> {
+    fn parse_moves(moves:&str) -> Result<Vec<Move>, MoveError> {
         moves.lines()
-            .map(|line| Move::from_str(line).unwrap_or_else(|e| panic!("{e}")) )
+            .map(Move::from_str)
             .collect()
     }
 }
-#[derive(Debug)]
+impl Display for Move {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "move {} from {} to {}", self.count, self.from, self.to)
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+enum MoveError {
+    Parse(String),
+    InvalidStack(String),
+    SameStack(String),
+    Underflow { stack: String, requested: usize, available: usize }
+}
+impl Display for MoveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::Parse(line) => write!(f, "'{line}' is not a 'move <count> from <stack> to <stack>' line"),
+            MoveError::InvalidStack(s) => write!(f, "stack {s} does not exist"),
+            MoveError::SameStack(s) => write!(f, "cannot move stack {s} onto itself"),
+            MoveError::Underflow { stack, requested, available } =>
+                write!(f, "stack {stack} holds {available} crates, {requested} requested"),
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+enum DiagramError {
+    MissingLabels,
+    DuplicateLabel(String),
+    UnclosedCrate { line: usize, pos: usize },
+    FloatingCrate { line: usize, name: String }
+}
+impl Display for DiagramError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiagramError::MissingLabels => write!(f, "diagram has no stack label row"),
+            DiagramError::DuplicateLabel(l) => write!(f, "stack label {l} appears more than once"),
+            DiagramError::UnclosedCrate { line, pos } => write!(f, "line {line}: crate at column {pos} has no closing ']'"),
+            DiagramError::FloatingCrate { line, name } => write!(f, "line {line}: crate [{name}] has nothing underneath"),
+        }
+    }
+}
+
+type Crate = String;
+
+/// A crane lifts up to `capacity()` crates at a time, repeating until the whole move is carried out
+trait Crane {
+    /// Maximum crates per lift; `None` lifts the whole move at once
+    fn capacity(&self) -> Option<usize>;
+    /// Whether the crates lifted together keep their order when put down
+    fn keeps_order(&self) -> bool;
+    fn operate(&self, from: &mut Vec<Crate>, to: &mut Vec<Crate>, count: usize) {
+        let lift = self.capacity().unwrap_or(count).max(1);
+        let mut left = count;
+        while left > 0 {
+            let load = from.split_off(from.len() - left.min(lift));
+            left -= load.len();
+            if self.keeps_order() {
+                to.extend(load);
+            } else {
+                to.extend(load.into_iter().rev());
+            }
+        }
+    }
+}
+/// Moves one crate at a time
+struct CrateMover9000;
+impl Crane for CrateMover9000 {
+    fn capacity(&self) -> Option<usize> { Some(1) }
+    fn keeps_order(&self) -> bool { false }
+}
+/// Moves all crates at once, keeping their order
+struct CrateMover9001;
+impl Crane for CrateMover9001 {
+    fn capacity(&self) -> Option<usize> { None }
+    fn keeps_order(&self) -> bool { true }
+}
+/// User defined crane that lifts up to `capacity` crates at a time
+struct CapacityCrane {
+    capacity: usize,
+    keeps_order: bool
+}
+impl Crane for CapacityCrane {
+    fn capacity(&self) -> Option<usize> { Some(self.capacity) }
+    fn keeps_order(&self) -> bool { self.keeps_order }
+}
+
+#[derive(Debug,Clone,PartialEq)]
 struct Buckets {
-    buckets: HashMap<usize,Vec<char>>,
-    keys: Vec<usize>
+    buckets: Vec<Vec<Crate>>,
+    keys: Vec<String>
 }
-impl Buckets {
-    fn new(start: &str) -> Buckets {
-        let buckets = start.lines()
-            .rev()
-            .map(|line| line.split("").filter_map(|e| e.chars().next()).collect::<Vec<_>>())
-            .fold(HashMap::new(), |map, e| {
-                e.into_iter()
+impl FromStr for Buckets {
+    type Err = DiagramError;
+
+    /// The label row gives the stack columns; each bracketed crate above
+    /// is assigned to the stack whose label is nearest to the crate's centre
+    fn from_str(start: &str) -> Result<Self, Self::Err> {
+        let rows = start.lines().collect::<Vec<_>>();
+        let mut lines = rows.into_iter().enumerate().rev();
+
+        // label row; each label gives the centre of its stack column
+        let (label_line, labels) = lines.next().ok_or(DiagramError::MissingLabels)?;
+        let columns = columns(labels);
+        if columns.is_empty() {
+            return Err(DiagramError::MissingLabels);
+        }
+        let keys = columns.iter().map(|(_, l)| l.clone()).collect::<Vec<_>>();
+        if let Some((_, dup)) = keys.iter().enumerate().find(|(i, k)| keys[..*i].contains(k)) {
+            return Err(DiagramError::DuplicateLabel(dup.clone()));
+        }
+
+        let mut buckets = vec![Vec::new(); keys.len()];
+        for (line, row) in lines {
+            let level = label_line - line - 1;
+            let row = row.chars().collect::<Vec<_>>();
+            let mut pos = 0;
+            while let Some(open) = row[pos..].iter().position(|&c| c == '[').map(|p| pos + p) {
+                let close = row[open..].iter().position(|&c| c == ']')
+                    .map(|p| open + p)
+                    .ok_or(DiagramError::UnclosedCrate { line: line + 1, pos: open + 1 })?;
+                let name = row[open+1..close].iter().collect::<String>();
+                let (stack, _) = columns.iter()
                     .enumerate()
-                    .filter(|(_, c)| c.is_alphanumeric())
-                    .fold(map, |mut out, (key, val)| {
-                        out.entry(key)
-                            .or_insert(Vec::default())
-                            .push(val);
-                        out
-                    })
-            });
-        let mut keys = buckets.keys().copied().collect::<Vec<_>>();
-        keys.sort();
-        Buckets {
-            buckets,
-            keys
+                    .min_by_key(|(_, (centre, _))| centre.abs_diff(open + close))
+                    .expect("columns cannot be empty");
+                if buckets[stack].len() != level {
+                    return Err(DiagramError::FloatingCrate { line: line + 1, name });
+                }
+                buckets[stack].push(name);
+                pos = close + 1;
+            }
         }
+        Ok(Buckets { buckets, keys })
     }
-    fn crate_mover9000(&mut self, m: Move) {
-        let (from, to) = self.get_keys(m);
-        (0..m.count)
-            .for_each(|_|{
-                if let Some(c) = self.buckets.get_mut(&from).expect("").pop() {
-                    self.buckets.get_mut(&to).expect("").push(c)
-                }
-        });
+}
+/// Splits the label row into labels, each along with the doubled position of its centre
+fn columns(labels: &str) -> Vec<(usize, String)> {
+    let row = labels.chars().collect::<Vec<_>>();
+    let mut out = vec![];
+    let mut pos = 0;
+    while let Some(start) = row[pos..].iter().position(|c| !c.is_whitespace()).map(|p| pos + p) {
+        let end = row[start..].iter().position(|c| c.is_whitespace()).map_or(row.len(), |p| start + p);
+        out.push((start + end - 1, row[start..end].iter().collect()));
+        pos = end;
+    }
+    out
+}
+impl Buckets {
+    fn new(start: &str) -> Buckets {
+        Buckets::from_str(start).unwrap_or_else(|e| panic!("{e}"))
+    }
+    fn apply(&mut self, crane: &impl Crane, m: &Move) -> Result<(), MoveError> {
+        let (from, to) = self.get_keys(m)?;
+        let available = self.buckets[from].len();
+        if m.count > available {
+            return Err(MoveError::Underflow { stack: m.from.clone(), requested: m.count, available });
+        }
+        let mut src = std::mem::take(&mut self.buckets[from]);
+        crane.operate(&mut src, &mut self.buckets[to], m.count);
+        self.buckets[from] = src;
+        Ok(())
     }
-    fn crate_mover9001(&mut self, m: Move) {
-        let (from, to) = self.get_keys(m);
-        let v = (0..m.count)
-            .fold(vec![],|mut out,_|{
-                if let Some(c) = self.buckets.get_mut(&from).expect("").pop() { out.push(c) }
-                out
-            });
-        self.buckets.get_mut(&to).expect("").extend(v.iter().rev());
+    /// Runs the moves against a copy of the current state; the observer is called after each step
+    fn simulate<F>(&self, crane: &impl Crane, moves: &[Move], mut observe: F) -> Result<Buckets, MoveError>
+        where F: FnMut(&Move, &Buckets) {
+        moves.iter()
+            .try_fold(self.clone(), |mut state, m| {
+                state.apply(crane, m)?;
+                observe(m, &state);
+                Ok(state)
+            })
     }
     fn scoop_top(&self) -> String {
-        self.keys.iter()
-            .filter_map(|key| self.buckets.get(key))
-            .filter_map(|arr| arr.last().copied() )
-            .fold(String::new(),|mut out,s| { out.push(s); out })
+        self.buckets.iter()
+            .filter_map(|arr| arr.last().cloned() )
+            .collect()
     }
-    fn get_keys(&self, m:Move) -> (usize,usize) {
-        (self.keys[m.from-1],self.keys[m.to-1])
+    /// Moves refer to stacks by label
+    fn get_keys(&self, m: &Move) -> Result<(usize,usize), MoveError> {
+        let key = |k: &String| self.keys.iter()
+            .position(|label| label == k)
+            .ok_or(MoveError::InvalidStack(k.clone()));
+        match (key(&m.from)?, key(&m.to)?) {
+            (from, to) if from == to => Err(MoveError::SameStack(m.from.clone())),
+            keys => Ok(keys)
+        }
+    }
+}
+impl Display for Buckets {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = self.buckets.iter()
+            .flatten()
+            .map(|c| c.chars().count() + 2)
+            .chain(self.keys.iter().map(|k| k.chars().count()))
+            .max()
+            .unwrap_or(3);
+        let height = self.buckets.iter().map(Vec::len).max().unwrap_or(0);
+        for level in (0..height).rev() {
+            let line = self.buckets.iter()
+                .map(|stack| format!("{:^width$}", stack.get(level).map(|c| format!("[{c}]")).unwrap_or_default()))
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(f, "{line}")?;
+        }
+        let labels = self.keys.iter()
+            .map(|k| format!("{k:^width$}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        write!(f, "{labels}")
     }
 }
 
 fn main() {
 
     let data = std::fs::read_to_string("src/bin/day5_input.txt").expect("Ops!");
+    let trace = std::env::args().any(|arg| arg == "--trace");
 
     let [start,moves] = data.split("\n\n").collect::<Vec<_>>()[..] else { panic!("") };
 
-    let mut buckets = Buckets::new(start);
-    let moves = Move::parse_moves(moves);
+    let buckets = Buckets::new(start);
+    let moves = Move::parse_moves(moves).unwrap_or_else(|e| panic!("{e}"));
+
+    let print_step = |m: &Move, b: &Buckets| if trace { println!("{m}\n{b}\n") };
 
-    moves.iter().for_each(|&m| buckets.crate_mover9000(m) );
-    println!("{:?}",buckets.scoop_top());
+    let report = |name: &str, result: Result<Buckets, MoveError>| match result {
+        Ok(end) => println!("{name}: {:?}\n{end}\n", end.scoop_top()),
+        Err(e) => println!("{name}: {e}")
+    };
+
+    report("CrateMover 9000", buckets.simulate(&CrateMover9000, &moves, print_step));
+    report("CrateMover 9001", buckets.simulate(&CrateMover9001, &moves, print_step));
+    report("CrateMover 3x", buckets.simulate(&CapacityCrane { capacity: 3, keeps_order: true }, &moves, print_step));
+}
 
-    moves.iter().for_each(|&m| buckets.crate_mover9001(m) );
-    println!("{:?}",buckets.scoop_top());
+#[cfg(test)]
+mod test {
+    use super::*;
 
+    const INPUT: &str = "    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 \n\nmove 1 from 2 to 1\nmove 3 from 1 to 3\nmove 2 from 2 to 1\nmove 1 from 1 to 2";
+
+    #[test]
+    fn test_cranes() {
+        let [start, moves] = INPUT.split("\n\n").collect::<Vec<_>>()[..] else { panic!() };
+        let buckets = Buckets::new(start);
+        let moves = Move::parse_moves(moves).unwrap();
+
+        assert_eq!(buckets.to_string(), start);
+        assert_eq!(buckets.simulate(&CrateMover9000, &moves, |_, _| {}).unwrap().scoop_top(), "CMZ");
+        assert_eq!(buckets.simulate(&CrateMover9001, &moves, |_, _| {}).unwrap().scoop_top(), "MCD");
+        assert_eq!(buckets.simulate(&CapacityCrane { capacity: 1, keeps_order: true }, &moves, |_, _| {}).unwrap().scoop_top(), "CMZ");
+    }
+    #[test]
+    fn test_invalid_moves() {
+        let mut buckets = Buckets::new("[Z] [M] [P]\n 1   2   3 ");
+        let m = |line: &str| Move::from_str(line).unwrap();
+        assert_eq!(buckets.apply(&CrateMover9000, &m("move 1 from 4 to 1")), Err(MoveError::InvalidStack("4".to_string())));
+        assert_eq!(buckets.apply(&CrateMover9000, &m("move 2 from 1 to 2")), Err(MoveError::Underflow { stack: "1".to_string(), requested: 2, available: 1 }));
+        assert_eq!(buckets.apply(&CrateMover9000, &m("move 1 from 2 to 2")), Err(MoveError::SameStack("2".to_string())));
+        assert_eq!(buckets.to_string(), "[Z] [M] [P]\n 1   2   3 ");
+
+        assert_eq!(Move::parse_moves("move 1 from 1 to 2\nmove x from 1 to 2"), Err(MoveError::Parse("move x from 1 to 2".to_string())));
+        assert_eq!(Move::from_str("move 1 from 1"), Err(MoveError::Parse("move 1 from 1".to_string())));
+        assert_eq!(Move::from_str("lift 1 from 1 to 2"), Err(MoveError::Parse("lift 1 from 1 to 2".to_string())));
+    }
+    #[test]
+    fn test_diagram_round_trip() {
+        let diagram = "[A]                                     [B]\n 1   2   3   4   5   6   7   8   9  10  11 ";
+        let buckets = Buckets::from_str(diagram).unwrap();
+        assert_eq!(buckets.keys.len(), 11);
+        assert_eq!(buckets.to_string(), diagram);
+        let end = buckets.simulate(&CrateMover9000, &Move::parse_moves("move 1 from 11 to 1").unwrap(), |_, _| {}).unwrap();
+        assert_eq!(end.scoop_top(), "B");
+
+        let diagram = "[AB]     \n[CD] [E] \n 1    2  ";
+        let buckets = Buckets::from_str(diagram).unwrap();
+        assert_eq!(buckets.buckets, vec![vec!["CD".to_string(), "AB".to_string()], vec!["E".to_string()]]);
+        assert_eq!(buckets.to_string(), diagram);
+
+        assert_eq!(Buckets::from_str("[A\n 1 "), Err(DiagramError::UnclosedCrate { line: 1, pos: 1 }));
+        assert_eq!(Buckets::from_str("[A]\n    \n 1 "), Err(DiagramError::FloatingCrate { line: 1, name: "A".to_string() }));
+        assert_eq!(Buckets::from_str(" 1   1 "), Err(DiagramError::DuplicateLabel("1".to_string())));
+    }
+    #[test]
+    fn test_named_stacks() {
+        let buckets = Buckets::new("[X]         \n[Y] [Z]     \n A   B   C ");
+        let moves = Move::parse_moves("move 2 from A to C\nmove 1 from B to A").unwrap();
+        assert_eq!(buckets.simulate(&CrateMover9000, &moves, |_, _| {}).unwrap().scoop_top(), "ZY");
+        assert_eq!(buckets.simulate(&CrateMover9001, &moves, |_, _| {}).unwrap().scoop_top(), "ZX");
+        assert_eq!(
+            buckets.simulate(&CrateMover9000, &Move::parse_moves("move 1 from A to 1").unwrap(), |_, _| {}),
+            Err(MoveError::InvalidStack("1".to_string()))
+        );
+    }
 }
@@ -1,34 +1,160 @@
-use std::fmt::Debug;
+use std::collections::VecDeque;
+use std::io::{BufReader, Read};
 
-trait Duplicate {
-    fn has_duplicates(&self) -> bool;
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Marker {
+    len: usize,
+    pos: usize
 }
-impl<T> Duplicate for [T] where T: Debug + Copy + PartialEq + Ord {
-    fn has_duplicates(&self) -> bool {
-        let mut tmp = self.to_vec();
-        tmp.sort();
-        tmp.windows(2).any(|a| a[0]==a[1])
+
+/// Rolling count table over the last `len` bytes of the stream
+struct Window {
+    len: usize,
+    counts: [usize; 256],
+    dups: usize
+}
+impl Window {
+    fn new(len: usize) -> Window {
+        Window { len, counts: [0; 256], dups: 0 }
+    }
+    fn enter(&mut self, b: u8) {
+        self.counts[b as usize] += 1;
+        if self.counts[b as usize] == 2 { self.dups += 1 }
+    }
+    fn leave(&mut self, b: u8) {
+        self.counts[b as usize] -= 1;
+        if self.counts[b as usize] == 1 { self.dups -= 1 }
+    }
+}
+
+/// Streaming marker detector; tracks any number of window lengths in a single pass, O(1) per byte and window
+struct Detector {
+    windows: Vec<Window>,
+    history: VecDeque<u8>,
+    max_len: usize,
+    pos: usize
+}
+impl Detector {
+    fn new(lens: &[usize]) -> Detector {
+        let max = lens.iter().copied().max().unwrap_or(0);
+        Detector {
+            windows: lens.iter().map(|&len| Window::new(len)).collect(),
+            history: VecDeque::with_capacity(max + 1),
+            max_len: max,
+            pos: 0
+        }
+    }
+    /// Feed the next byte and return the markers ending at it
+    fn push(&mut self, b: u8) -> Vec<Marker> {
+        self.pos += 1;
+        self.history.push_back(b);
+        let (history, pos) = (&self.history, self.pos);
+        let markers = self.windows.iter_mut()
+            .filter(|w| w.len > 0)
+            .filter_map(|w| {
+                w.enter(b);
+                if history.len() > w.len {
+                    w.leave(history[history.len() - 1 - w.len]);
+                }
+                (pos >= w.len && w.dups == 0).then_some(Marker { len: w.len, pos })
+            })
+            .collect();
+        if self.history.len() > self.max_len {
+            self.history.pop_front();
+        }
+        markers
+    }
+    /// All markers found in the stream, in stream order
+    fn markers(mut self, stream: impl IntoIterator<Item=u8>) -> impl Iterator<Item=Marker> {
+        stream.into_iter().flat_map(move |b| self.push(b))
+    }
+    /// All markers found in any reader, buffered internally, in stream order;
+    /// an I/O error is passed on as the last item
+    fn read(mut self, reader: impl Read) -> impl Iterator<Item=std::io::Result<Marker>> {
+        BufReader::new(reader).bytes()
+            .scan(false, |failed, b| (!*failed).then(|| { *failed = b.is_err(); b }))
+            .flat_map(move |b| match b {
+                Ok(b) => self.push(b).into_iter().map(Ok).collect::<Vec<_>>(),
+                Err(e) => vec![Err(e)]
+            })
     }
 }
 
 trait Signaling {
-    fn marker_position(&self, len:usize) -> usize;
+    fn marker_position(&self, len:usize) -> Option<usize>;
 }
-impl<T> Signaling for [T] where T : Debug + Copy + PartialEq + Ord {
-    fn marker_position(&self, len: usize) -> usize {
-        self.windows(len)
-            .enumerate()
-            .skip_while(|&(_,stm)| stm.has_duplicates() )
+impl Signaling for [u8] {
+    fn marker_position(&self, len: usize) -> Option<usize> {
+        Detector::new(&[len])
+            .markers(self.iter().copied())
             .next()
-            .map(|(i,_)| i + len)
-            .unwrap_or_else(|| panic!("marker_position(): Ops!"))
+            .map(|m| m.pos)
     }
 }
 
-fn main() {
-    let data = std::fs::read_to_string("src/bin/day6_input.txt").expect("");
+fn main() -> std::io::Result<()> {
+    let data = std::fs::read_to_string("src/bin/day6_input.txt")?;
 
     let out = data.bytes().collect::<Vec<_>>();
-    println!("Marker Length @4 = {}", out.marker_position(4));
-    println!("Marker Length @14 = {}", out.marker_position(14));
-}
\ No newline at end of file
+    println!("Marker Length @4 = {:?}", out.marker_position(4));
+    println!("Marker Length @14 = {:?}", out.marker_position(14));
+
+    let file = std::fs::File::open("src/bin/day6_input.txt")?;
+    let markers = Detector::new(&[4, 14])
+        .read(file)
+        .collect::<std::io::Result<Vec<_>>>()?;
+    [4, 14].into_iter()
+        .for_each(|len| println!("Markers @{len} = {}", markers.iter().filter(|m| m.len == len).count()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_first_marker() {
+        let samples = [
+            ("mjqjpqmgbljsphdztnvjfqwrcgsmlb", 7, 19),
+            ("bvwbjplbgvbhsrlpgdmjqwftvncz", 5, 23),
+            ("nppdvjthqldpwncqszvftbrmjlhg", 6, 23),
+            ("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg", 10, 29),
+            ("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 11, 26)
+        ];
+        samples.into_iter()
+            .for_each(|(s, a, b)| {
+                assert_eq!(s.as_bytes().marker_position(4), Some(a));
+                assert_eq!(s.as_bytes().marker_position(14), Some(b));
+            });
+        assert_eq!(b"aaaaaaa".marker_position(2), None);
+    }
+    #[test]
+    fn test_all_markers() {
+        let markers = Detector::new(&[2, 3])
+            .read("aabcc".as_bytes())
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(markers, vec![
+            Marker { len: 2, pos: 3 },
+            Marker { len: 2, pos: 4 },
+            Marker { len: 3, pos: 4 },
+        ]);
+    }
+    #[test]
+    fn test_read_error() {
+        /// Hands out its bytes, then fails on every read
+        struct Failing(&'static [u8]);
+        impl Read for Failing {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if self.0.is_empty() {
+                    return Err(std::io::Error::other("broken"))
+                }
+                self.0.read(buf)
+            }
+        }
+        let out = Detector::new(&[2]).read(Failing(b"aab")).collect::<Vec<_>>();
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].as_ref().unwrap(), &Marker { len: 2, pos: 3 });
+        assert!(out[1].is_err());
+    }
+}
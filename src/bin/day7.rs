@@ -1,5 +1,7 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::OnceCell;
+use std::collections::BTreeMap;
+use num_integer::Integer;
+use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
 #[derive(Debug, Clone)]
@@ -17,86 +19,595 @@ enum LineType {
     Cmd(CommandType),
     Rst(ResultType)
 }
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
-struct Path(String);
+
+/// Absolute path held as its list of components; the root is the empty list
+#[derive(Debug, Clone, Default, Hash, Eq, PartialEq, Ord, PartialOrd)]
+struct Path(Vec<String>);
 impl Path {
-    fn new(path:String) -> Path {
-        Path(path)
+    fn root() -> Path {
+        Path::default()
     }
     fn append(&self, dir: &str) -> Path {
-        Path(format!("{}{}",self.0,dir))
+        let mut path = self.clone();
+        path.0.push(dir.to_string());
+        path
+    }
+    fn parent(&self) -> Path {
+        let mut path = self.clone();
+        path.0.pop();
+        path
+    }
+    /// Resolves an absolute or relative target, e.g. `/a/b`, `..`, `./c/../d`, against this path
+    fn resolve(&self, target: &str) -> Path {
+        let start = if target.starts_with('/') { Path::root() } else { self.clone() };
+        target.split('/')
+            .fold(start, |path, part| match part {
+                "" | "." => path,
+                ".." => path.parent(),
+                name => path.append(name)
+            })
+    }
+}
+impl Display for Path {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "/")
+        } else {
+            self.0.iter().try_for_each(|part| write!(f, "/{part}"))
+        }
     }
 }
+impl FromStr for Path {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('/') {
+            Ok(Path::root().resolve(s))
+        } else {
+            Err(format!("'{s}' is not an absolute path"))
+        }
+    }
+}
+
+type NodeId = usize;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Entry {
+    Dir(NodeId),
+    File(usize)
+}
 #[derive(Debug)]
 struct Node {
-    parent: Path,
-    content: Vec<ResultType>,
+    parent: NodeId,
+    name: String,
+    content: BTreeMap<String, Entry>,
     size: usize
 }
+impl Node {
+    fn new(parent: NodeId, name: &str) -> Node {
+        Node { parent, name: name.to_string(), content: BTreeMap::new(), size: 0 }
+    }
+}
+
+/// In-memory file system; directories are kept in an arena with the root at index 0.
+/// Directory totals are computed on first use and kept until the tree changes
 #[derive(Debug)]
 struct Tree {
-    map: HashMap<Path,Node>,
-    totals: RefCell<Vec<(Path,usize)>>
+    nodes: Vec<Node>,
+    totals: OnceCell<Vec<usize>>
+}
+impl Default for Tree {
+    fn default() -> Self {
+        Tree { nodes: vec![Node::new(Tree::ROOT, "/")], totals: OnceCell::new() }
+    }
 }
 impl Tree {
-    fn children(&self, path: &Path) -> Vec<&String> {
-        self.map[path]
-            .content
-            .iter()
-            .filter_map(|rt|
-                if let ResultType::Dir(dir) = rt {
-                    Some(dir)
-                } else {
-                    None
+    const ROOT: NodeId = 0;
+
+    /// Path of a directory, rebuilt from its parent links
+    fn path(&self, id: NodeId) -> Path {
+        let mut parts = std::iter::successors(Some(id), |&id| Some(self.nodes[id].parent))
+            .take_while(|&id| id != Tree::ROOT)
+            .map(|id| self.nodes[id].name.clone())
+            .collect::<Vec<_>>();
+        parts.reverse();
+        Path(parts)
+    }
+    fn lookup(&self, path: &Path) -> Option<NodeId> {
+        path.0.iter()
+            .try_fold(Tree::ROOT, |id, part| match self.nodes[id].content.get(part) {
+                Some(&Entry::Dir(child)) => Some(child),
+                _ => None
+            })
+    }
+    /// Returns the directory under `parent`, creating it if needed; a file of the same name is replaced
+    fn mkdir(&mut self, parent: NodeId, name: &str) -> NodeId {
+        match self.nodes[parent].content.get(name) {
+            Some(&Entry::Dir(id)) => id,
+            _ => {
+                self.totals.take();
+                let id = self.nodes.len();
+                self.nodes.push(Node::new(parent, name));
+                let node = &mut self.nodes[parent];
+                if let Some(Entry::File(old)) = node.content.insert(name.to_string(), Entry::Dir(id)) {
+                    node.size -= old;
                 }
-            )
-            .collect()
+                id
+            }
+        }
     }
-    fn dir_size(&self, path: &Path) -> usize {
-        self.map[path].size
+    /// Empties a directory that has been replaced, along with everything underneath it
+    fn clear(&mut self, id: NodeId) {
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let node = &mut self.nodes[id];
+            node.size = 0;
+            stack.extend(std::mem::take(&mut node.content).into_values()
+                .filter_map(|e| if let Entry::Dir(child) = e { Some(child) } else { None }));
+        }
     }
-    fn totals(&self) -> Vec<(Path, usize)> {
-        self.totals.take()
+    /// Resolves an absolute or relative target against the `cwd` directory, creating directories as needed
+    fn chdir(&mut self, cwd: NodeId, target: &str) -> NodeId {
+        let start = if target.starts_with('/') { Tree::ROOT } else { cwd };
+        target.split('/')
+            .fold(start, |id, part| match part {
+                "" | "." => id,
+                ".." => self.nodes[id].parent,
+                name => self.mkdir(id, name)
+            })
+    }
+    /// Adds or replaces a file, so that listing a directory twice does not count its files twice;
+    /// a directory of the same name is replaced along with its content
+    fn touch(&mut self, dir: NodeId, name: &str, fsize: usize) {
+        self.totals.take();
+        let node = &mut self.nodes[dir];
+        match node.content.insert(name.to_string(), Entry::File(fsize)) {
+            Some(Entry::File(old)) => node.size -= old,
+            Some(Entry::Dir(id)) => self.clear(id),
+            None => {}
+        }
+        self.nodes[dir].size += fsize;
+    }
+    fn children(&self, id: NodeId) -> impl Iterator<Item=(&String, NodeId)> {
+        self.nodes[id].content.iter()
+            .filter_map(|(name, e)| if let Entry::Dir(child) = e { Some((name, *child)) } else { None })
+    }
+    fn dir_size(&self, id: NodeId) -> usize {
+        self.nodes[id].size
     }
     fn parse_history(history: impl Iterator<Item=LineType>) -> Tree {
         use LineType::*;
 
-        let mut map = HashMap::<Path,Node>::new();
-        let mut path = Path::new("".to_string());
+        let mut tree = Tree::default();
+        let mut cwd = Tree::ROOT;
 
         history
-            // .inspect(|line| println!("{:?}",line))
             .for_each(|lt| {
                 match lt {
-                    Cmd(CommandType::Cd(dir)) if dir.contains("..") => path = map[&path].parent.clone(),
-                    Cmd(CommandType::Cd(dir)) => {
-                        let cpath = path.append(dir.as_str());
-                        println!("{:?}",cpath);
-                        map.entry(cpath.clone())
-                            .or_insert(Node { parent: path.clone(), content: Vec::new(), size: 0 });
-                        path = cpath;
-                    }
-                    Rst(res) => {
-                        let node = map.get_mut(&path).unwrap();
-                        node.content.push(res.clone());
-                        if let ResultType::File(_,fsize) = res {
-                            node.size += fsize;
-                        }
-                    }
+                    Cmd(CommandType::Cd(dir)) => cwd = tree.chdir(cwd, dir.as_str()),
+                    Rst(ResultType::Dir(dir)) => { tree.mkdir(cwd, dir.as_str()); },
+                    Rst(ResultType::File(name, fsize)) => tree.touch(cwd, name.as_str(), fsize),
                     Cmd(CommandType::List) => {},
                 }
             });
-        Tree { map, totals: RefCell::new(Vec::new()) }
+        tree
+    }
+    /// Total size of a directory and everything underneath it
+    fn du(&self, path: &Path) -> Option<usize> {
+        self.lookup(path).map(|id| self.total(id))
+    }
+    fn total(&self, id: NodeId) -> usize {
+        self.totals.get_or_init(|| self.calc_dirs_totals())[id]
+    }
+    /// Post-order traversal with an explicit stack, so that deep trees cannot overflow the call stack;
+    /// a directory is summed up once all of its children have been
+    fn calc_dirs_totals(&self) -> Vec<usize> {
+        let mut totals = (0..self.nodes.len()).map(|id| self.dir_size(id)).collect::<Vec<_>>();
+        let mut stack = vec![(Tree::ROOT, false)];
+        while let Some((id, expanded)) = stack.pop() {
+            if expanded {
+                totals[id] += self.children(id).map(|(_, child)| totals[child]).sum::<usize>();
+            } else {
+                stack.push((id, true));
+                stack.extend(self.children(id).map(|(_, child)| (child, false)));
+            }
+        }
+        totals
+    }
+    /// Every entry in the tree in depth-first order, along with its depth and name; the root is `/` at depth 0.
+    /// Paths are not built here so that deep trees stay linear, see `path` and `find`
+    fn walk(&self) -> Vec<(usize, &str, Entry)> {
+        let mut out = vec![];
+        let mut stack = vec![(0, "/", Entry::Dir(Tree::ROOT))];
+        while let Some((depth, name, entry)) = stack.pop() {
+            if let Entry::Dir(id) = entry {
+                stack.extend(
+                    self.nodes[id].content.iter().rev().map(|(name, e)| (depth + 1, name.as_str(), *e))
+                );
+            }
+            out.push((depth, name, entry));
+        }
+        out
+    }
+    /// Entries matching the predicate; one path is kept up to date along the walk and cloned on a match
+    fn find<F>(&self, predicate: F) -> Vec<(Path, Entry)> where F: Fn(&Path, &Entry) -> bool {
+        let mut path = Path::root();
+        self.walk().into_iter()
+            .filter_map(|(depth, name, e)| {
+                path.0.truncate(depth.saturating_sub(1));
+                if depth > 0 { path.0.push(name.to_string()) }
+                predicate(&path, &e).then(|| (path.clone(), e))
+            })
+            .collect()
+    }
+    /// Every directory along with its total size, in path order
+    fn totals(&self) -> Vec<(NodeId, usize)> {
+        self.walk().into_iter()
+            .filter_map(|(_, _, e)| match e {
+                Entry::Dir(id) => Some((id, self.total(id))),
+                Entry::File(_) => None
+            })
+            .collect()
+    }
+    /// Every directory by total size, largest first; ties stay in path order
+    fn by_size(&self) -> Vec<(NodeId, usize)> {
+        let mut dirs = self.totals();
+        dirs.sort_by_key(|&(_, size)| std::cmp::Reverse(size));
+        dirs
+    }
+    /// The `n` largest directories by total size
+    fn largest(&self, n: usize) -> Vec<(Path, usize)> {
+        self.by_size().into_iter()
+            .take(n)
+            .map(|(id, size)| (self.path(id), size))
+            .collect()
+    }
+}
+/// Pretty prints the tree in the puzzle's format, i.e. `- a (dir)` / `- b.txt (file, size=14848514)`
+impl Display for Tree {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.walk().iter()
+            .try_for_each(|(depth, name, e)| {
+                let indent = "  ".repeat(*depth);
+                match e {
+                    Entry::Dir(_) => writeln!(f, "{indent}- {name} (dir)"),
+                    Entry::File(fsize) => writeln!(f, "{indent}- {name} (file, size={fsize})"),
+                }
+            })
+    }
+}
+
+/// Exporters; both walk the tree with an explicit stack
+impl Tree {
+    /// Nested JSON of the tree, e.g. `{"name":"/","type":"dir","size":48381165,"children":[...]}`
+    fn to_json(&self) -> String {
+        fn quote(s: &str) -> String {
+            s.chars()
+                .fold(String::from("\""), |mut out, c| {
+                    match c {
+                        '"' => out.push_str("\\\""),
+                        '\\' => out.push_str("\\\\"),
+                        c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+                        c => out.push(c)
+                    }
+                    out
+                }) + "\""
+        }
+        let mut out = String::new();
+        // open directories; whether the next child written is their first
+        let mut open: Vec<bool> = vec![];
+        for (depth, name, e) in self.walk() {
+            while open.len() > depth {
+                open.pop();
+                out.push_str("]}");
+            }
+            if let Some(first) = open.last_mut() {
+                if !*first { out.push(',') }
+                *first = false;
+            }
+            match e {
+                Entry::Dir(id) => {
+                    out.push_str(&format!("{{\"name\":{},\"type\":\"dir\",\"size\":{},\"children\":[", quote(name), self.total(id)));
+                    open.push(true);
+                }
+                Entry::File(fsize) =>
+                    out.push_str(&format!("{{\"name\":{},\"type\":\"file\",\"size\":{fsize}}}", quote(name))),
+            }
+        }
+        out.push_str(&"]}".repeat(open.len()));
+        out
     }
-    fn calc_dirs_totals(&self, path: &Path) -> usize {
-        let mut sum = self.dir_size(path);
-        for dir in self.children(path) {
-            let cpath = path.append(dir);
-            sum += self.calc_dirs_totals(&cpath);
+    /// Canonical `$ cd` / `$ ls` transcript that parses back into the same tree
+    fn to_transcript(&self) -> String {
+        enum Step<'a> { Enter(&'a str, NodeId), Leave }
+
+        let mut out = String::new();
+        let mut stack = vec![Step::Enter("/", Tree::ROOT)];
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Enter(name, id) => {
+                    out.push_str(&format!("$ cd {name}\n$ ls\n"));
+                    self.nodes[id].content.iter()
+                        .for_each(|(name, e)| match e {
+                            Entry::Dir(_) => out.push_str(&format!("dir {name}\n")),
+                            Entry::File(fsize) => out.push_str(&format!("{fsize} {name}\n")),
+                        });
+                    if id != Tree::ROOT { stack.push(Step::Leave) }
+                    stack.extend(self.children(id).collect::<Vec<_>>().into_iter().rev().map(|(name, child)| Step::Enter(name, child)));
+                }
+                Step::Leave => out.push_str("$ cd ..\n")
+            }
         }
-        // println!("{:?}:{:?}", path, sum);
-        self.totals.borrow_mut().push((path.clone(), sum));
-        sum
+        out
+    }
+}
+
+/// Xorshift pseudo random generator; good enough for building test transcripts
+struct Rng(u64);
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    fn range(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+    fn name(&mut self) -> String {
+        (0..3 + self.range(6)).map(|_| char::from(b'a' + self.range(26) as u8)).collect()
+    }
+}
+
+/// Random transcript of a file system with up to `dirs` directories nested at most `depth` deep and `files` files
+fn generate(depth: usize, dirs: usize, files: usize, seed: u64) -> String {
+    let mut rng = Rng(seed.max(1));
+    let mut tree = Tree::default();
+    let mut levels = vec![0];
+    (0..dirs).for_each(|_| {
+        let parent = (0..4)
+            .map(|_| rng.range(levels.len()))
+            .find(|&p| levels[p] < depth);
+        if let Some(parent) = parent {
+            let name = rng.name();
+            let id = tree.mkdir(parent, name.as_str());
+            if id == levels.len() { levels.push(levels[parent] + 1) }
+        }
+    });
+    (0..files).for_each(|_| {
+        let dir = rng.range(levels.len());
+        let name = format!("{}.{}", rng.name(), &rng.name()[..3]);
+        tree.touch(dir, name.as_str(), 1 + rng.range(400000));
+    });
+    tree.to_transcript()
+}
+
+/// Formats a byte count the way `du -h` does, i.e. `584`, `2.5K`, `14M`
+fn human(bytes: usize) -> String {
+    let units = ["", "K", "M", "G", "T"];
+    let (mut val, mut unit) = (bytes as f64, 0);
+    while val >= 1024.0 && unit < units.len() - 1 {
+        val /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes}"),
+        _ if val < 10.0 => format!("{:.1}{}", val, units[unit]),
+        _ => format!("{:.0}{}", val, units[unit])
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Minimise { Bytes, Count }
+impl FromStr for Minimise {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bytes" => Ok(Minimise::Bytes),
+            "count" => Ok(Minimise::Count),
+            _ => Err(format!("'{s}' is neither 'bytes' nor 'count'"))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Plan {
+    delete: Vec<(Path, usize)>,
+    freed: usize
+}
+
+/// Disk usage reporting and deletion planning against a device of given capacity
+struct Disk<'a> {
+    tree: &'a Tree,
+    capacity: usize
+}
+impl Disk<'_> {
+    fn new(tree: &Tree, capacity: usize) -> Disk<'_> {
+        Disk { tree, capacity }
+    }
+    fn used(&self) -> usize {
+        self.tree.du(&Path::root()).unwrap_or(0)
+    }
+    /// `du -h` style listing of every directory, largest first, with its share of the used space
+    fn report(&self) -> String {
+        let used = self.used().max(1);
+        self.tree.by_size().iter()
+            .map(|&(id, size)| format!("{:>6} {:>6.2}% {}\n", human(size), size as f64 * 100.0 / used as f64, self.tree.path(id)))
+            .chain([format!("{:>6} of {} used, {} free\n", human(self.used()), human(self.capacity), human(self.capacity.saturating_sub(self.used())))])
+            .collect()
+    }
+    /// Bytes that must be deleted so that at least `free` bytes become available
+    fn shortfall(&self, free: usize) -> usize {
+        (free + self.used()).saturating_sub(self.capacity)
+    }
+    /// Finds the set of directories to delete so that at least `free` bytes become available.
+    /// Nested directories are never picked together, as deleting the parent already deletes the child.
+    /// The root directory is not a candidate.
+    fn plan(&self, free: usize, minimise: Minimise) -> Option<Plan> {
+        let needed = self.shortfall(free);
+        if needed == 0 {
+            return Some(Plan { delete: vec![], freed: 0 });
+        }
+        let cand = Candidates::new(self.tree);
+        let by_count = cand.min_count(needed)?;
+        let picks = match minimise {
+            Minimise::Count => by_count,
+            Minimise::Bytes => cand.min_bytes(needed, by_count)
+        };
+        Some(Plan {
+            freed: cand.bytes(&picks),
+            delete: picks.into_iter().map(|i| (self.tree.path(cand.dirs[i].0), cand.dirs[i].1)).collect()
+        })
+    }
+}
+
+/// Non-root directories in pre-order, each with its total size and the index right past its subtree,
+/// so that picking index `i` means jumping to `end[i]` while skipping it means moving to `i+1`
+struct Candidates {
+    dirs: Vec<(NodeId, usize)>,
+    end: Vec<usize>
+}
+impl Candidates {
+    fn new(tree: &Tree) -> Candidates {
+        let (mut dirs, mut end) = (vec![], vec![]);
+        // directories whose subtree is still being walked, with their depth
+        let mut open: Vec<(usize, usize)> = vec![];
+        for (depth, _, e) in tree.walk() {
+            let Entry::Dir(id) = e else { continue };
+            if id == Tree::ROOT { continue }
+            while open.last().is_some_and(|&(_, d)| d >= depth) {
+                let (i, _) = open.pop().unwrap();
+                end[i] = dirs.len();
+            }
+            open.push((dirs.len(), depth));
+            dirs.push((id, tree.total(id)));
+            end.push(0);
+        }
+        open.iter().for_each(|&(i, _)| end[i] = dirs.len());
+        Candidates { dirs, end }
+    }
+    fn bytes(&self, picks: &[usize]) -> usize {
+        picks.iter().map(|&i| self.dirs[i].1).sum()
+    }
+    /// Fewest directories freeing at least `needed` bytes; ties are resolved towards fewer bytes
+    fn min_count(&self, needed: usize) -> Option<Vec<usize>> {
+        let n = self.dirs.len();
+        // most[i][k]: most bytes freed with exactly k picks out of positions i..n
+        let mut most = vec![vec![None; n + 1]; n + 1];
+        most[n][0] = Some(0);
+        for i in (0..n).rev() {
+            for k in 0..=n {
+                let take = k.checked_sub(1)
+                    .and_then(|k| most[self.end[i]][k])
+                    .map(|b: usize| b + self.dirs[i].1);
+                most[i][k] = most[i+1][k].max(take);
+            }
+        }
+        let k = (1..=n).find(|&k| most[0][k].is_some_and(|b| b >= needed))?;
+
+        // branch & bound over exactly k picks, bounded by `most`, for the fewest bytes
+        let mut best = (usize::MAX, vec![]);
+        let mut picks = vec![];
+        self.search(0, k, 0, needed, &most, &mut picks, &mut best);
+        Some(best.1)
+    }
+    #[allow(clippy::too_many_arguments)]
+    fn search(&self, i: usize, k: usize, sum: usize, needed: usize, most: &[Vec<Option<usize>>], picks: &mut Vec<usize>, best: &mut (usize, Vec<usize>)) {
+        if sum >= best.0 || most[i][k].is_none_or(|m| sum + m < needed) {
+            return
+        }
+        if k == 0 {
+            *best = (sum, picks.clone());
+            return
+        }
+        picks.push(i);
+        self.search(self.end[i], k - 1, sum + self.dirs[i].1, needed, most, picks, best);
+        picks.pop();
+        self.search(i + 1, k, sum, needed, most, picks, best);
+    }
+    /// Fewest bytes freeing at least `needed`, starting from the known feasible `seed` picks
+    fn min_bytes(&self, needed: usize, seed: Vec<usize>) -> Vec<usize> {
+        let mut search = BytesSearch::new(self, needed, seed);
+        if search.best.0 > search.target {
+            search.search(0, 0);
+        }
+        search.best.1
+    }
+}
+
+/// Branch & bound for the fewest bytes, trying the largest directories first so that the sum closes in on
+/// `needed` by ever smaller steps. Ties keep the pre-order, hence a directory always comes after its ancestors
+/// and is skipped once one of them is picked. Every sum is a multiple of the sizes' greatest common divisor,
+/// so the search stops as soon as `needed` rounded up to that multiple is found
+struct BytesSearch<'a> {
+    cand: &'a Candidates,
+    needed: usize,
+    target: usize,
+    order: Vec<usize>,
+    parent: Vec<Option<usize>>,
+    // rest[k]: bytes of the directories in order[k..] whose parent is not, the most that is left to free
+    rest: Vec<usize>,
+    picked: Vec<bool>,
+    picks: Vec<usize>,
+    best: (usize, Vec<usize>)
+}
+impl BytesSearch<'_> {
+    fn new(cand: &Candidates, needed: usize, seed: Vec<usize>) -> BytesSearch<'_> {
+        let n = cand.dirs.len();
+        let size = |i: usize| cand.dirs[i].1;
+        let mut order = (0..n).filter(|&i| size(i) > 0).collect::<Vec<_>>();
+        order.sort_by_key(|&i| std::cmp::Reverse(size(i)));
+
+        let mut parent = vec![None; n];
+        let mut children = vec![vec![]; n];
+        let mut open: Vec<usize> = vec![];
+        for (i, end) in cand.end.iter().enumerate() {
+            while open.last().is_some_and(|&p| cand.end[p] <= i) {
+                open.pop();
+            }
+            parent[i] = open.last().copied();
+            if let Some(p) = parent[i] { children[p].push(i) }
+            if *end > i + 1 { open.push(i) }
+        }
+        let mut rest = vec![(0..n).filter(|&i| parent[i].is_none()).map(size).sum::<usize>()];
+        for &i in &order {
+            let last = rest[rest.len() - 1];
+            rest.push(last - size(i) + children[i].iter().map(|&c| size(c)).sum::<usize>());
+        }
+        let gcd = order.iter().fold(0, |g, &i| g.gcd(&size(i))).max(1);
+        let target = needed.div_ceil(gcd) * gcd;
+        BytesSearch { cand, needed, target, order, parent, rest, picked: vec![false; n], picks: vec![], best: (cand.bytes(&seed), seed) }
+    }
+    fn blocked(&self, i: usize) -> bool {
+        std::iter::successors(self.parent[i], |&p| self.parent[p]).any(|p| self.picked[p])
+    }
+    /// Decides `order[k..]` having freed `sum` so far; true once nothing better can be found
+    fn search(&mut self, k: usize, sum: usize) -> bool {
+        if sum >= self.best.0 {
+            return false
+        }
+        if sum >= self.needed {
+            let mut picks = self.picks.clone();
+            picks.sort();
+            self.best = (sum, picks);
+            return sum == self.target
+        }
+        if k == self.order.len() || sum + self.rest[k] < self.needed {
+            return false
+        }
+        let i = self.order[k];
+        if !self.blocked(i) {
+            self.picked[i] = true;
+            self.picks.push(i);
+            let done = self.search(k + 1, sum + self.cand.dirs[i].1);
+            self.picks.pop();
+            self.picked[i] = false;
+            if done {
+                return true
+            }
+        }
+        self.search(k + 1, sum)
     }
 }
 
@@ -121,33 +632,216 @@ impl History {
 
 fn main() {
 
-    let history = std::fs::read_to_string("src/bin/day7_input.txt").expect("");
+    let opt = |name: &str| std::env::args()
+        .skip_while(|a| a != name)
+        .nth(1);
+    let arg = |name: &str, default: usize| opt(name)
+        .map(|v| usize::from_str(&v).unwrap_or_else(|e| panic!("{name}: {e}")))
+        .unwrap_or(default);
+    let flag = |name: &str| std::env::args().any(|a| a == name);
+
+    if flag("--generate") {
+        print!("{}", generate(arg("--depth", 8), arg("--generate", 200), arg("--files", 1000), arg("--seed", 2022) as u64));
+        return
+    }
+
+    // transcript from the first argument unless it is an option, `-` being stdin, e.g. `day7 --generate 1000 | day7 -`
+    let history = match std::env::args().nth(1).filter(|a| !a.starts_with("--")).as_deref() {
+        Some("-") => std::io::read_to_string(std::io::stdin()).unwrap_or_else(|e| panic!("stdin: {e}")),
+        path => {
+            let path = path.unwrap_or("src/bin/day7_input.txt");
+            std::fs::read_to_string(path).unwrap_or_else(|e| panic!("{path}: {e}"))
+        }
+    };
 
     let tree = Tree::parse_history(
         History::iterator(history.as_str())
     );
 
-    tree.calc_dirs_totals(&Path::new("/".to_string()));
+    if flag("--json") {
+        println!("{}", tree.to_json());
+        return
+    }
+    if flag("--transcript") {
+        print!("{}", tree.to_transcript());
+        return
+    }
+
     let dirs = tree.totals();
 
     println!("Directories < 100000 \n====================");
     println!("{:?}",
              dirs.iter()
                  .filter(|(_,size)| *size < 100000 )
-                 .inspect(|&p| println!("{:?}",p))
+                 .inspect(|&&(id,size)| println!("{} {size}", tree.path(id)))
                  .map(|&(_,size)| size)
                  .sum::<usize>()
     );
 
-    let total_space = 70000000;
-    let min_free_space = 30000000;
-    let &(_,total_used) = dirs.last().unwrap();
-    let min_space_to_free = min_free_space - (total_space - total_used);
-    println!("Directories ~ 30000000 \n====================");
+    let disk = Disk::new(&tree, arg("--capacity", 70000000));
+    let min_free_space = arg("--free", 30000000);
+
+    if flag("--report") {
+        print!("{}", disk.report());
+    }
+
+    let min_space_to_free = disk.shortfall(min_free_space);
+    println!("Directories ~ {min_free_space} \n====================");
     println!("{:?}",
              dirs.iter()
                  .filter(|(_,size)| *size >= min_space_to_free )
-                 .inspect(|&p| println!("{:?}",p))
+                 .inspect(|&&(id,size)| println!("{} {size}", tree.path(id)))
                  .min_by(|&a,&b| a.1.cmp(&b.1))
+                 .map(|&(id,size)| format!("{} {size}", tree.path(id)))
     );
-}
\ No newline at end of file
+
+    let minimise = opt("--minimise").map_or(Minimise::Count, |v| Minimise::from_str(&v).unwrap_or_else(|e| panic!("--minimise: {e}")));
+    println!("Deletion plan minimising {minimise:?} \n====================");
+    match disk.plan(min_free_space, minimise) {
+        Some(plan) => {
+            plan.delete.iter().for_each(|(path, size)| println!("{size:>10} {path}"));
+            println!("{:>10} freed of {min_space_to_free} needed", plan.freed);
+        }
+        None => println!("Cannot free {min_free_space} bytes")
+    }
+
+    println!("Files > 300000 \n====================");
+    tree.find(|_, e| matches!(e, Entry::File(fsize) if *fsize > 300000))
+        .iter()
+        .for_each(|(path, e)| println!("{path} {e:?}"));
+
+    println!("Largest directories \n====================");
+    tree.largest(5).iter()
+        .for_each(|(path, size)| println!("{size:>10} {path}"));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const INPUT: &str = "$ cd /\n$ ls\ndir a\n14848514 b.txt\n8504156 c.dat\ndir d\n$ cd a\n$ ls\ndir e\n29116 f\n2557 g\n62596 h.lst\n$ cd e\n$ ls\n584 i\n$ cd ..\n$ cd ..\n$ cd d\n$ ls\n4060174 j\n8033020 d.log\n5626152 d.ext\n7214296 k";
+
+    #[test]
+    fn test_sample_tree() {
+        let tree = Tree::parse_history(History::iterator(INPUT));
+        assert_eq!(tree.du(&Path::root()), Some(48381165));
+        assert_eq!(tree.du(&"/a/e".parse().unwrap()), Some(584));
+        assert_eq!(tree.largest(2), vec![(Path::root(), 48381165), ("/d".parse().unwrap(), 24933642)]);
+        assert_eq!(tree.find(|p, _| p.0.last().is_some_and(|name| name == "i")), vec![("/a/e/i".parse().unwrap(), Entry::File(584))]);
+        assert!(tree.to_string().starts_with("- / (dir)\n  - a (dir)\n    - e (dir)\n      - i (file, size=584)\n"));
+    }
+    #[test]
+    fn test_paths_and_relisting() {
+        // "a"+"bc" and "ab"+"c" must be different directories, listing twice must not count twice
+        let input = "$ cd /\n$ ls\ndir a\ndir ab\n$ cd a\n$ ls\ndir bc\n$ cd bc\n$ ls\n10 x\n$ cd /ab\n$ ls\ndir c\n$ cd c\n$ ls\n20 y\n$ ls\n20 y\n$ cd ../../a/./bc";
+        let tree = Tree::parse_history(History::iterator(input));
+        assert_eq!(tree.du(&"/a/bc".parse().unwrap()), Some(10));
+        assert_eq!(tree.du(&"/ab/c".parse().unwrap()), Some(20));
+        assert_eq!(tree.du(&Path::root()), Some(30));
+        assert_eq!(Path::root().resolve("/a/b").resolve("../c").to_string(), "/a/c");
+    }
+    #[test]
+    fn test_file_dir_conflicts() {
+        // the latest listing wins, whichever kind came first
+        let input = "$ cd /\n$ ls\ndir x\n5 y\n$ cd x\n$ ls\n100 z\n$ cd ..\n$ ls\n123 x\n5 y";
+        let tree = Tree::parse_history(History::iterator(input));
+        assert_eq!(tree.du(&Path::root()), Some(128));
+        assert_eq!(tree.du(&"/x".parse().unwrap()), None);
+        assert_eq!(tree.totals(), vec![(Tree::ROOT, 128)]);
+        assert!(tree.nodes.iter().skip(1).all(|n| n.size == 0 && n.content.is_empty()));
+
+        let input = "$ cd /\n$ ls\n123 x\n5 y\n$ ls\ndir x\n5 y\n$ cd x\n$ ls\n100 z";
+        let tree = Tree::parse_history(History::iterator(input));
+        assert_eq!(tree.du(&Path::root()), Some(105));
+        assert_eq!(tree.du(&"/x".parse().unwrap()), Some(100));
+        assert_eq!(tree.find(|_, e| matches!(e, Entry::File(_))).len(), 2);
+    }
+    #[test]
+    fn test_deletion_plan() {
+        let tree = Tree::parse_history(History::iterator(INPUT));
+        let disk = Disk::new(&tree, 70000000);
+        assert_eq!(disk.shortfall(30000000), 8381165);
+        assert_eq!(disk.plan(30000000, Minimise::Count), Some(Plan { delete: vec![("/d".parse().unwrap(), 24933642)], freed: 24933642 }));
+        assert_eq!(disk.plan(100, Minimise::Bytes), Some(Plan { delete: vec![], freed: 0 }));
+        // without /d being large enough, several directories must go; /a/e is never picked alongside /a
+        let disk = Disk::new(&tree, 48381165);
+        assert_eq!(disk.plan(94000, Minimise::Bytes).map(|p| p.freed), Some(94853));
+        assert_eq!(disk.plan(24933642 + 94853, Minimise::Count).map(|p| p.delete.len()), Some(2));
+        assert_eq!(disk.plan(24933642 + 94854, Minimise::Count), None);
+        assert_eq!("bytes".parse(), Ok(Minimise::Bytes));
+        assert!("fewest".parse::<Minimise>().is_err());
+        assert!(disk.report().starts_with("   46M 100.00% /\n   24M  51.54% /d\n"));
+    }
+    #[test]
+    fn test_large_capacity_plan() {
+        let input = "$ cd /\n$ ls\ndir a\ndir b\ndir c\ndir d\n$ cd a\n$ ls\n100000000 f\ndir e\n$ cd e\n$ ls\n60000000 f\n\
+            $ cd /b\n$ ls\n70000000 f\n$ cd /c\n$ ls\n45000000 f\n$ cd /d\n$ ls\n30000001 f";
+        let tree = Tree::parse_history(History::iterator(input));
+        let disk = Disk::new(&tree, 2_000_000_000);
+        let free = 2_000_000_000 - disk.used() + 175_000_000;
+        assert_eq!(disk.shortfall(free), 175_000_000);
+        assert_eq!(disk.plan(free, Minimise::Count).map(|p| p.freed), Some(190_000_001));
+        assert_eq!(disk.plan(free, Minimise::Bytes), Some(Plan {
+            delete: vec![("/a/e".parse().unwrap(), 60_000_000), ("/b".parse().unwrap(), 70_000_000), ("/c".parse().unwrap(), 45_000_000)],
+            freed: 175_000_000
+        }));
+    }
+    #[test]
+    fn test_generated_tree_plan() {
+        let transcript = generate(8, 1000, 1000, 2022);
+        let tree = Tree::parse_history(History::iterator(transcript.as_str()));
+        assert!(tree.totals().len() > 1000);
+        let disk = Disk::new(&tree, 70000000);
+        let by_count = disk.plan(30000000, Minimise::Count).unwrap();
+        let by_bytes = disk.plan(30000000, Minimise::Bytes).unwrap();
+        assert_eq!(by_count.delete.len(), 2);
+        assert_eq!(by_bytes.freed, disk.shortfall(30000000));
+        assert_eq!(by_bytes.freed, by_bytes.delete.iter().map(|(_, size)| size).sum::<usize>());
+        assert!(by_bytes.delete.iter().all(|(a, _)| by_bytes.delete.iter().all(|(b, _)| a == b || !b.0.starts_with(&a.0))));
+
+        // no exact sum exists once every size is even and an odd number of bytes is needed
+        let mut tree = tree;
+        tree.nodes.iter_mut().for_each(|node| node.size *= 2);
+        tree.totals.take();
+        let disk = Disk::new(&tree, 70000001);
+        assert_eq!(disk.shortfall(30000000) % 2, 1);
+        assert_eq!(disk.plan(30000000, Minimise::Bytes).map(|p| p.freed), Some(disk.shortfall(30000000) + 1));
+    }
+    #[test]
+    fn test_deep_tree_totals() {
+        let depth = 100000;
+        let input = "$ cd d\n$ ls\ndir d\n1 f\n".repeat(depth);
+        let tree = Tree::parse_history(History::iterator(input.as_str()));
+        assert_eq!(tree.du(&Path::root()), Some(depth));
+        assert_eq!(tree.du(&Path::root()), Some(depth));
+        assert_eq!(tree.du(&"/d/d".parse().unwrap()), Some(depth - 1));
+
+        let totals = tree.totals();
+        assert_eq!(totals.len(), depth + 2);
+        assert_eq!(totals[2], (tree.lookup(&"/d/d".parse().unwrap()).unwrap(), depth - 1));
+        assert_eq!(tree.path(totals[depth].0).0.len(), depth);
+        assert_eq!(tree.largest(2), vec![(Path::root(), depth), ("/d".parse().unwrap(), depth)]);
+        assert_eq!(tree.find(|p, e| p.0.len() == 3 && *e == Entry::File(1)), vec![("/d/d/f".parse().unwrap(), Entry::File(1))]);
+        assert_eq!(tree.to_json().matches(r#"{"name":"f","type":"file","size":1}"#).count(), depth);
+    }
+    #[test]
+    fn test_exports() {
+        let tree = Tree::parse_history(History::iterator(INPUT));
+        assert!(tree.to_json().starts_with(r#"{"name":"/","type":"dir","size":48381165,"children":[{"name":"a","type":"dir","size":94853,"children":[{"name":"e","type":"dir","size":584,"children":[{"name":"i","type":"file","size":584}]},"#));
+        assert!(tree.to_json().ends_with(r#"{"name":"k","type":"file","size":7214296}]}]}"#));
+
+        let transcript = tree.to_transcript();
+        assert!(transcript.starts_with("$ cd /\n$ ls\ndir a\n14848514 b.txt\n8504156 c.dat\ndir d\n$ cd a\n$ ls\ndir e\n"));
+        let replay = Tree::parse_history(History::iterator(transcript.as_str()));
+        assert_eq!(replay.to_json(), tree.to_json());
+        assert_eq!(replay.to_transcript(), transcript);
+    }
+    #[test]
+    fn test_generated_transcript() {
+        let transcript = generate(5, 50, 200, 7);
+        let tree = Tree::parse_history(History::iterator(transcript.as_str()));
+        assert!(tree.walk().iter().all(|(depth, _, _)| *depth <= 6));
+        assert!((190..=200).contains(&tree.find(|_, e| matches!(e, Entry::File(_))).len()));
+        assert_eq!(tree.to_transcript(), transcript);
+    }
+}
@@ -1,115 +1,247 @@
+use std::io::Write;
+use bracket_lib::prelude::{RgbLerp, RGB, NAVY, YELLOW, RED, ORANGE, WHITE, BLACK};
+use advent2022::{Coord, Grid};
 
-type ScanSequence = Vec<Vec<Coord>>;
+/// Step between neighbouring trees along a line of sight
+type Direction = (isize, isize);
+const ORTHOGONAL: [Direction; 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const DIAGONAL: [Direction; 4] = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
 
-#[derive(Debug,Copy, Clone)]
-struct Coord {
-    x: usize,
-    y: usize
+/// Decides whether a tree blocks the view of a viewer; must not turn false as the tree grows taller
+trait Blocking {
+    fn blocks(&self, tree: u8, viewer: u8) -> bool;
 }
-impl From<(usize,usize)> for Coord {
-    fn from(p: (usize, usize)) -> Self {
-        Coord { x:p.0, y:p.1 }
+impl<F> Blocking for F where F: Fn(u8, u8) -> bool {
+    fn blocks(&self, tree: u8, viewer: u8) -> bool {
+        self(tree, viewer)
     }
 }
-
-#[derive(Debug)]
-struct Grid<T> {
-    width: usize,
-    height: usize,
-    grid: Vec<T>,
-}
-impl<T> Grid<T> where T : Default + Copy {
-    fn new(height: usize, width: usize) -> Grid<T> {
-        Grid {
-            height,
-            width,
-            grid: vec![T::default(); width * height]
-        }
-    }
-    fn in_bounds(&self, p:Coord) -> bool {
-        p.x < self.width && p.y < self.height
+/// Trees at least as tall as the viewer block its view
+struct AsTall;
+impl Blocking for AsTall {
+    fn blocks(&self, tree: u8, viewer: u8) -> bool {
+        tree >= viewer
     }
-    fn tree(&self, p: Coord) -> Option<&T> {
-        if !self.in_bounds(p) {
-            return None
-        }
-        Some(&self.grid[p.y * self.width + p.x])
+}
+/// Only strictly taller trees block the view
+struct Taller;
+impl Blocking for Taller {
+    fn blocks(&self, tree: u8, viewer: u8) -> bool {
+        tree > viewer
     }
-    fn tree_mut(&mut self, p: Coord) -> Option<&mut T> {
-        if !self.in_bounds(p) {
-            return None
-        }
-        Some(&mut self.grid[p.y * self.width + p.x])
+}
+/// The viewer looks from the given height above its own top; trees reaching that high block its view
+struct Raised(u8);
+impl Blocking for Raised {
+    fn blocks(&self, tree: u8, viewer: u8) -> bool {
+        tree as u16 >= viewer as u16 + self.0 as u16
     }
 }
 
-#[derive(Debug)]
-struct Visibility<'a> {
-    forest: &'a Grid<i32>,
-    visible: Grid<bool>,
+/// Visibility and scenic score of every tree, computed with one monotonic stack pass per direction.
+/// Scanning a line, the stack holds the trees not overshadowed by a nearer one at least as tall,
+/// hence in decreasing height; the blocking trees sit at the bottom, so the view back from the current tree
+/// ends at the topmost of them, while none left means the current tree is visible from the edge the scan started from.
+struct Survey {
+    /// bit `i` is set when the tree sees out to the edge along `dirs[i]`
+    visible: Grid<u16>,
+    scenic: Grid<usize>,
+    dirs: Vec<Direction>,
+    rule: Box<dyn Blocking>,
 }
-impl Visibility<'_> {
-    fn new(forest: &Grid<i32>) -> Visibility {
-        Visibility {
-            forest,
-            visible: Grid::new(forest.width, forest.height),
-        }
+impl Survey {
+    fn new(forest: &Grid<u8>) -> Survey {
+        Survey::with(forest, &ORTHOGONAL, AsTall)
+    }
+    fn with(forest: &Grid<u8>, dirs: &[Direction], rule: impl Blocking + 'static) -> Survey {
+        assert!(dirs.len() <= u16::BITS as usize, "at most {} directions", u16::BITS);
+        assert!(!dirs.contains(&(0, 0)), "(0, 0) is not a direction");
+
+        let (width, height) = (forest.width, forest.height);
+        let mut survey = Survey {
+            visible: Grid::new(width, height),
+            scenic: Grid::new(width, height),
+            dirs: dirs.to_vec(),
+            rule: Box::new(rule),
+        };
+        survey.scenic.iter_mut().for_each(|s| *s = 1);
+
+        // every line starts at the edge the trees look out to and runs back against the direction
+        dirs.iter().enumerate()
+            .for_each(|(bit, &(dx, dy))| {
+                coords(forest)
+                    .filter(|&p| step(forest, p, (dx, dy)).is_none())
+                    .for_each(|start| {
+                        let line = std::iter::successors(Some(start), |&p| step(forest, p, (-dx, -dy)));
+                        survey.scan_line(forest, bit, line);
+                    })
+            });
+        survey
+    }
+    fn scan_line(&mut self, forest: &Grid<u8>, bit: usize, line: impl Iterator<Item=Coord>) {
+        let mut stack: Vec<(u8, usize)> = Vec::new();
+        line.enumerate()
+            .for_each(|(i, p)| {
+                let &height = forest.square(p).unwrap();
+                let blocking = stack.partition_point(|&(h, _)| self.rule.blocks(h, height));
+                // distance to the blocking tree, or to the edge
+                *self.scenic.square_mut(p).unwrap() *= match blocking {
+                    0 => i,
+                    n => i - stack[n - 1].1
+                };
+                if blocking == 0 {
+                    *self.visible.square_mut(p).unwrap() |= 1 << bit;
+                }
+                while stack.last().is_some_and(|&(h, _)| h <= height) {
+                    stack.pop();
+                }
+                stack.push((height, i));
+            });
+    }
+    fn is_visible(&self, p: Coord) -> bool {
+        self.visible.square(p).is_some_and(|&mask| mask != 0)
+    }
+    /// Directions along which the tree sees out to the edge
+    fn visible_from(&self, p: Coord) -> Vec<Direction> {
+        let &mask = self.visible.square(p).unwrap_or(&0);
+        self.dirs.iter().enumerate()
+            .filter(|&(bit, _)| mask & 1 << bit != 0)
+            .map(|(_, &d)| d)
+            .collect()
     }
     fn count_visible(&self) -> usize {
-        self.visible.grid.iter()
-            .filter(|&e| *e)
+        self.visible.iter()
+            .filter(|&&mask| mask != 0)
             .count()
     }
-    fn scan_visibility(&mut self, direction: ScanSequence) -> &mut Self {
-        direction.into_iter()
-            .for_each(|pos| {
-                let mut tallest = -1;
-                pos.into_iter().for_each(|e| {
-                    let tree = self.visible.tree_mut(e).unwrap();
-                    let t= self.forest.tree(e).unwrap();
-                    if tallest.lt(t) {
-                        tallest = *t;
-                        *tree = true;
-                    }
-                });
-            });
-        self
+    fn max_scenic(&self) -> Option<usize> {
+        self.scenic.iter().max().copied()
     }
+    /// Trees along `dir` from `p`, up to and including the one blocking its view
+    fn sight_line<'a>(&'a self, forest: &'a Grid<u8>, p: Coord, dir: Direction) -> impl Iterator<Item=Coord> + 'a {
+        let &height = forest.square(p).unwrap_or(&0);
+        let mut blocked = false;
+        std::iter::successors(step(forest, p, dir), move |&q| step(forest, q, dir))
+            .map_while(move |q| {
+                if blocked { return None }
+                blocked = self.rule.blocks(*forest.square(q).unwrap(), height);
+                Some(q)
+            })
+    }
+    /// Trees seen by an observer standing at `at` with eyes at height `eye`, looking out along every direction;
+    /// a nearer tree hides one further out when it blocks a viewer as tall as the higher of the eyes and the far tree
+    fn observe(&self, forest: &Grid<u8>, at: Coord, eye: u8) -> Vec<Coord> {
+        self.dirs.iter()
+            .flat_map(|&dir| {
+                let mut tallest = None;
+                std::iter::successors(step(forest, at, dir), move |&q| step(forest, q, dir))
+                    .filter(move |&q| {
+                        let &height = forest.square(q).unwrap();
+                        let seen = tallest.is_none_or(|t| !self.rule.blocks(t, height.max(eye)));
+                        tallest = tallest.max(Some(height));
+                        seen
+                    })
+            })
+            .collect()
+    }
+}
+
+/// The neighbour of `p` along `dir`, if still within the forest
+fn step(forest: &Grid<u8>, p: Coord, (dx, dy): Direction) -> Option<Coord> {
+    let q = Coord { x: p.x.checked_add_signed(dx)?, y: p.y.checked_add_signed(dy)? };
+    (q.x < forest.width && q.y < forest.height).then_some(q)
 }
-#[derive(Debug)]
-struct Scenic<'a> {
-    forest: &'a Grid<i32>,
-    // scenic: Grid<usize>
+fn coords(forest: &Grid<u8>) -> impl Iterator<Item=Coord> {
+    let width = forest.width;
+    (0..forest.height).flat_map(move |y| (0..width).map(move |x| Coord { x, y }))
 }
-impl Scenic<'_> {
-    fn new(forest: &Grid<i32>) -> Scenic {
-        Scenic { forest }
-    }
-    fn scenic_score_dir(&mut self, p:Coord, (dx,dy):(isize,isize)) -> usize {
-        let line = (1..).map_while(|i| {
-            let coord = Coord {
-                x: p.x.checked_add_signed(dx * i)?,
-                y: p.y.checked_add_signed(dy * i)?,
-            };
-            self.forest.tree(coord)
-        });
-
-        let mut total = 0;
-        let our_height = self.forest.tree(p).unwrap();
-        for height in line {
-            total += 1;
-            if height >= our_height {
-                break;
-            }
-        }
-        total
 
+/// Renders a survey as text, CSV or PPM image, highlighting the best tree and its sight lines
+struct Heatmap<'a> {
+    forest: &'a Grid<u8>,
+    survey: &'a Survey,
+    best: Coord,
+    sight: Vec<Coord>,
+    max: usize
+}
+impl Heatmap<'_> {
+    fn new<'a>(forest: &'a Grid<u8>, survey: &'a Survey) -> Heatmap<'a> {
+        let best = coords(forest)
+            .max_by_key(|&p| (survey.scenic.square(p), std::cmp::Reverse(p)))
+            .unwrap_or(Coord { x: 0, y: 0 });
+        let sight = survey.dirs.iter()
+            .flat_map(|&dir| survey.sight_line(forest, best, dir))
+            .collect();
+        Heatmap { forest, survey, best, sight, max: survey.max_scenic().unwrap_or(0).max(1) }
+    }
+    fn scenic(&self, p: Coord) -> usize {
+        *self.survey.scenic.square(p).unwrap()
+    }
+    fn visible(&self, p: Coord) -> bool {
+        self.survey.is_visible(p)
+    }
+    /// Scenic score scaled onto `0..steps`
+    fn level(&self, p: Coord, steps: usize) -> usize {
+        self.scenic(p) * (steps - 1) / self.max
     }
-    fn scenic_score(&mut self, p: Coord) -> usize {
-        let dirs =  [(-1, 0), (1, 0), (0, -1), (0, 1)];
-        dirs.into_iter()
-            .map(|dir| self.scenic_score_dir(p,dir) )
-            .product()
+    /// Scenic scores as a character ramp; the best tree is `X` and its sight lines `+`
+    fn scenic_text(&self) -> String {
+        let ramp = b" .:-=*%#@";
+        self.render_text(|p| ramp[self.level(p, ramp.len())] as char)
+    }
+    /// Visible trees as `#`, hidden ones as `.`; the best tree is `X` and its sight lines `+`
+    fn visible_text(&self) -> String {
+        self.render_text(|p| if self.visible(p) { '#' } else { '.' })
+    }
+    fn render_text(&self, cell: impl Fn(Coord) -> char) -> String {
+        coords(self.forest)
+            .fold(String::new(), |mut out, p| {
+                out.push(match p {
+                    _ if p == self.best => 'X',
+                    _ if self.sight.contains(&p) => '+',
+                    _ => cell(p)
+                });
+                if p.x == self.forest.width - 1 { out.push('\n') }
+                out
+            })
+    }
+    /// One row per tree
+    fn csv(&self) -> String {
+        coords(self.forest)
+            .fold(String::from("x,y,height,visible,scenic,highlight\n"), |mut out, p| {
+                let highlight = if p == self.best { "best" } else if self.sight.contains(&p) { "sight" } else { "" };
+                out.push_str(&format!("{},{},{},{},{},{highlight}\n",
+                    p.x, p.y, self.forest.square(p).unwrap(), self.visible(p) as u8, self.scenic(p)
+                ));
+                out
+            })
+    }
+    /// Binary PPM image, `scale` pixels per tree; scenic scores on a colour gradient or the visibility mask
+    fn ppm(&self, scale: usize, visibility: bool) -> Vec<u8> {
+        let gradient: Vec<RGB> = RgbLerp::new(NAVY.into(), YELLOW.into(), 256).collect();
+        let colour = |p: Coord| -> RGB {
+            match p {
+                _ if p == self.best => RED.into(),
+                _ if self.sight.contains(&p) => ORANGE.into(),
+                _ if visibility => if self.visible(p) { WHITE.into() } else { BLACK.into() },
+                _ => gradient[self.level(p, gradient.len())]
+            }
+        };
+        let (width, height) = (self.forest.width * scale, self.forest.height * scale);
+        let mut out = format!("P6\n{width} {height}\n255\n").into_bytes();
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| Coord { x: x / scale, y: y / scale }))
+            .map(colour)
+            .for_each(|c| out.extend([c.r, c.g, c.b].map(|v| (v * 255.0).round() as u8)));
+        out
+    }
+    fn export(&self, dir: &str) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(format!("{dir}/scenic.txt"), self.scenic_text())?;
+        std::fs::write(format!("{dir}/visible.txt"), self.visible_text())?;
+        std::fs::write(format!("{dir}/forest.csv"), self.csv())?;
+        std::fs::File::create(format!("{dir}/scenic.ppm"))?.write_all(&self.ppm(8, false))?;
+        std::fs::File::create(format!("{dir}/visible.ppm"))?.write_all(&self.ppm(8, true))
     }
 }
 
@@ -118,53 +250,89 @@ fn main() {
     let data = std::fs::read_to_string("src/bin/day8_input.txt").expect("Ops!");
 
     let grid = parse_forest(data.as_str());
+    let survey = Survey::new(&grid);
+
+    println!("Total Visible = {:?}", survey.count_visible());
+    println!("Max scenic = {:?}", survey.max_scenic());
 
-    let count = Visibility::new(&grid)
-        .scan_visibility(left_to_right(&grid))
-        .scan_visibility(top_to_bottom(&grid))
-        .scan_visibility(right_to_left(&grid))
-        .scan_visibility(bottom_to_up(&grid))
-        .count_visible();
-    println!("Total Visible = {:?}", count);
-
-    let mut scenic = Scenic::new(&grid);
-    let max = left_to_right(&grid).into_iter()
-        .flat_map(|x| x)
-        .map(|p| scenic.scenic_score(p))
-        .max().unwrap();
-    println!("Max scenic = {:?}", max);
+    let heatmap = Heatmap::new(&grid, &survey);
+    println!("Best tree @{:?}", heatmap.best);
+    if std::env::args().any(|arg| arg == "--heatmap") {
+        println!("{}\n{}", heatmap.scenic_text(), heatmap.visible_text());
+        let dir = "target/day8";
+        heatmap.export(dir).unwrap_or_else(|e| panic!("{dir}: {e}"));
+        println!("Heatmaps exported to {dir}/");
+    }
+
+    let &height = grid.square(heatmap.best).unwrap();
+    println!("Best tree sees out along {:?} and sees {} trees from its top",
+             survey.visible_from(heatmap.best), survey.observe(&grid, heatmap.best, height).len());
+
+    if std::env::args().any(|arg| arg == "--diagonal") {
+        let dirs = [ORTHOGONAL, DIAGONAL].concat();
+        [
+            ("as tall", Survey::with(&grid, &dirs, AsTall)),
+            ("taller", Survey::with(&grid, &dirs, Taller)),
+            ("raised by 2", Survey::with(&grid, &dirs, Raised(2)))
+        ]
+            .into_iter()
+            .for_each(|(name, survey)| println!("8 directions, blocked by {name}: visible = {}, max scenic = {:?}",
+                survey.count_visible(), survey.max_scenic()));
+    }
 }
 
-fn parse_forest(data: &str) -> Grid<i32>  {
+fn parse_forest(data: &str) -> Grid<u8>  {
     let width = data.lines().next().unwrap().len();
     let height = data.lines().count();
     let mut grid = Grid::new(width,height);
 
     for (y,line) in data.lines().enumerate() {
         for (x, val) in line.bytes().enumerate() {
-            *grid.tree_mut((x,y).into()).unwrap() = (val - b'0') as i32;
+            *grid.square_mut((x,y).into()).unwrap() = val - b'0';
         }
     }
     grid
 }
 
-fn left_to_right(f: &Grid<i32>) -> ScanSequence {
-    (0..f.height)
-        .map(|y| (0..f.width).map(move |x| (x, y).into()).collect::<Vec<Coord>>() )
-        .collect::<Vec<_>>()
-}
-fn right_to_left(f: &Grid<i32>) -> ScanSequence {
-    (0..f.height)
-        .map(|y| (0..f.width).rev().map(move |x| (x, y).into()).collect::<Vec<Coord>>() )
-        .collect::<Vec<_>>()
-}
-fn top_to_bottom(f: &Grid<i32>) -> ScanSequence {
-    (0..f.width)
-        .map(|x| (0..f.height).map(move |y| (x,y).into()).collect::<Vec<Coord>>() )
-        .collect::<Vec<_>>()
-}
-fn bottom_to_up(f: &Grid<i32>) -> ScanSequence {
-    (0..f.width)
-        .map(|x| (0..f.height).rev().map(move |y| (x,y).into()).collect::<Vec<Coord>>() )
-        .collect::<Vec<_>>()
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sample_forest() {
+        let grid = parse_forest("30373\n25512\n65332\n33549\n35390");
+        let survey = Survey::new(&grid);
+        assert_eq!(survey.count_visible(), 21);
+        assert_eq!(survey.max_scenic(), Some(8));
+        assert_eq!(survey.scenic.square((2,1).into()), Some(&4));
+        assert_eq!(survey.scenic.square((2,3).into()), Some(&8));
+    }
+    #[test]
+    fn test_heatmap() {
+        let grid = parse_forest("30373\n25512\n65332\n33549\n35390");
+        let survey = Survey::new(&grid);
+        let heatmap = Heatmap::new(&grid, &survey);
+        assert_eq!(heatmap.best, (2,3).into());
+        assert_eq!(heatmap.visible_text(), "#####\n##+.#\n##+##\n++X++\n##+##\n");
+        assert_eq!(heatmap.scenic_text(), "     \n .+. \n %+: \n++X++\n  +  \n");
+        assert!(heatmap.csv().contains("\n2,3,5,1,8,best\n"));
+        assert_eq!(heatmap.ppm(2, true).len(), "P6\n10 10\n255\n".len() + 10 * 10 * 3);
+    }
+    #[test]
+    fn test_generalised_visibility() {
+        let grid = parse_forest("30373\n25512\n65332\n33549\n35390");
+        let survey = Survey::with(&grid, &ORTHOGONAL, |tree, viewer| tree >= viewer);
+        assert_eq!(survey.count_visible(), 21);
+        assert_eq!(survey.visible_from((0,0).into()), vec![(-1, 0), (0, -1)]);
+        assert_eq!(survey.visible_from((1,1).into()), vec![(-1, 0), (0, -1)]);
+        assert!(survey.visible_from((2,2).into()).is_empty());
+
+        assert_eq!(Survey::with(&grid, &ORTHOGONAL, Taller).count_visible(), 23);
+        assert_eq!(Survey::with(&grid, &ORTHOGONAL, Raised(2)).count_visible(), 25);
+        let all = Survey::with(&grid, &[ORTHOGONAL, DIAGONAL].concat(), AsTall);
+        assert_eq!(all.count_visible(), 22);
+        assert_eq!(all.max_scenic(), Some(16));
+
+        assert_eq!(survey.observe(&grid, (2,3).into(), 5).len(), 7);
+    }
 }
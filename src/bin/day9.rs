@@ -1,183 +1,255 @@
-use std::collections::HashSet;
-use std::hash::Hash;
-use std::ops::Sub;
-use std::str::FromStr;
-use std::vec;
-
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-struct Coord {
-    x: isize,
-    y: isize
-}
-impl Sub for Coord {
-    type Output = Coord;
+use bracket_lib::prelude::*;
+use advent2022::{
+    app::{App, AppLevel, State},
+    rope::{Pos, Rope, parse_steps}
+};
 
-    fn sub(self, rhs: Self) -> Self::Output {
-        Coord {
-            x: self.x - rhs.x,
-            y: self.y - rhs.y,
-        }
+fn main() -> BResult<()> {
+//     let data = "R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2".to_string();
+//     let data = "R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20\n".to_string();
+
+    let data = std::fs::read_to_string("src/bin/day9_input.txt").expect("");
+
+    let steps = parse_steps(data.as_str()).unwrap_or_else(|e| panic!("{e}"));
+    let trace = std::env::args().any(|arg| arg == "--trace");
+
+    println!("2 Link Chain - Unique points: {}",
+             Rope::new(Pos::default(), 2)
+                 .run(&steps)
+                 .visited(1)
+    );
+    let mut rope = Rope::new(Pos::default(), 10);
+    if trace { rope = rope.recording() }
+    rope.run(&steps);
+    println!("10 Links Chain - Unique points: {}", rope.visited(9));
+    println!("Unique points per knot: {:?}", rope.visits());
+
+    if let Some(csv) = rope.trace_csv() {
+        let dir = "target/day9";
+        std::fs::create_dir_all(dir)
+            .and_then(|_| std::fs::write(format!("{dir}/trace.csv"), csv))
+            .unwrap_or_else(|e| panic!("{dir}: {e}"));
+        println!("Knot trajectories exported to {dir}/trace.csv");
     }
-}
-impl From<(isize,isize)> for Coord {
-    fn from(pos: (isize, isize)) -> Self {
-        Coord{x:pos.0, y:pos.1}
+
+    // rope moves in space; named moves gain F/B along z, or any <x,y,z> vector
+    if let Some(path) = std::env::args().skip_while(|arg| arg != "--3d").nth(1) {
+        let data = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{path}: {e}"));
+        let steps = parse_steps::<3>(data.as_str()).unwrap_or_else(|e| panic!("{path}: {e}"));
+        [2, 10].into_iter()
+            .for_each(|len| println!("3D {len} Links Chain - Unique points: {}",
+                Rope::new(Default::default(), len).run(&steps).visited(len - 1)));
     }
-}
-#[derive(Debug, Copy, Clone)]
-enum Command {
-    Left,
-    Right,
-    Up,
-    Down
-}
-#[derive(Debug, Copy, Clone)]
-struct Step {
-    cmd: Command,
-    units: isize
-}
 
-#[derive(Debug, Copy, Clone)]
-struct Link {
-    pos: Coord
-}
-impl Link {
-    fn new(pos:Coord) -> Link {
-        Link { pos }
-    }
-    fn move_to(&mut self, cmd: Command) -> Coord {
-        match cmd {
-            Command::Left => self.pos.x -= 1,
-            Command::Right => self.pos.x += 1,
-            Command::Up => self.pos.y += 1,
-            Command::Down => self.pos.y -= 1
-        }
-        self.position()
-    }
-    fn move_relative(&mut self, front: &Link) -> Coord {
-        let dist = front.position() - self.position();
-        let (dx,dy) = match (dist.x, dist.y) {
-            // overlapping
-            (0, 0) => (0, 0),
-            // touching up/left/down/right
-            (0, 1) | (1, 0) | (0, -1) | (-1, 0) => (0, 0),
-            // touching diagonally
-            (1, 1) | (1, -1) | (-1, 1) | (-1, -1) => (0, 0),
-            // need to move up/left/down/right
-            (0, 2) => (0, 1),
-            (0, -2) => (0, -1),
-            (2, 0) => (1, 0),
-            (-2, 0) => (-1, 0),
-            // need to move to the right diagonally
-            (2, 1) => (1, 1),
-            (2, -1) => (1, -1),
-            // need to move to the left diagonally
-            (-2, 1) => (-1, 1),
-            (-2, -1) => (-1, -1),
-            // need to move up/down diagonally
-            (1, 2) => (1, 1),
-            (-1, 2) => (-1, 1),
-            (1, -2) => (1, -1),
-            (-1, -2) => (-1, -1),
-            // need to move diagonally
-            (-2, -2) => (-1, -1),
-            (-2, 2) => (-1, 1),
-            (2, -2) => (1, -1),
-            (2, 2) => (1, 1),
-            _ => panic!("unhandled case: tail - head = {dist:?}"),
-        };
-        self.pos.x += dx;
-        self.pos.y += dy;
-        self.position()
-    }
-    fn position(&self) -> Coord {
-        self.pos
+    if !std::env::args().any(|arg| arg == "--animate") {
+        return Ok(())
     }
+    let ctx = BTermBuilder::simple(160, 100)?
+        .with_simple_console_no_bg(80, 50, "terminal8x8.png")
+        .with_fps_cap(60f32)
+        .with_title("Day9: Rope Bridge")
+        .build()?;
+
+    let moves = steps.iter()
+        .flat_map(|s| std::iter::repeat_n(s.delta, s.units))
+        .collect();
+    let mut app = App::init(Store::new(moves, 2), Levels::Menu);
+    app.register_level(Levels::Menu, Menu);
+    app.register_level(Levels::Rope, Animation { paused: true, speed: 1 });
+
+    main_loop(ctx, app)
 }
 
-struct Chain {
-    links: Vec<Link>
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+enum Levels { Menu, Rope }
+
+/// The rope part way through the head's moves, with the area it has covered so far
+struct Store {
+    moves: Vec<Pos>,
+    rope: Rope<2>,
+    tick: usize,
+    bounds: (Pos, Pos)
 }
-impl Chain {
-    fn new(pos:Coord, size:usize) -> Chain {
-        Chain {
-            links: vec![Link::new(pos); size]
-        }
+impl Store {
+    fn new(moves: Vec<Pos>, knots: usize) -> Store {
+        Store { moves, rope: Rope::new(Pos::default(), knots), tick: 0, bounds: Default::default() }
+    }
+    fn reset(&mut self, knots: usize) {
+        self.rope = Rope::new(Pos::default(), knots);
+        self.tick = 0;
+        self.bounds = Default::default();
+    }
+    /// Swap the rope for one with `knots` knots, replayed up to the current move
+    fn switch(&mut self, knots: usize) {
+        let tick = self.tick;
+        self.reset(knots);
+        while self.tick < tick && self.advance() {}
+    }
+    /// Apply the next head move; false once all moves are done
+    fn advance(&mut self) -> bool {
+        let Some(&delta) = self.moves.get(self.tick) else { return false };
+        self.rope.step(delta);
+        self.tick += 1;
+        let (min, max) = &mut self.bounds;
+        self.rope.knots().iter()
+            .for_each(|p| {
+                *min = Pos::from((min[0].min(p[0]), min[1].min(p[1])));
+                *max = Pos::from((max[0].max(p[0]), max[1].max(p[1])));
+            });
+        true
     }
-    fn move_to(&mut self, cmd: Command) -> Coord {
+    fn knots(&self) -> usize {
+        self.rope.knots().len()
+    }
+    /// Draw the covered area scaled down to fit the console; visited tail cells shaded, knots on top
+    fn draw(&self, ctx: &mut BTerm) {
+        let (min, max) = self.bounds;
+        let (width, height) = ctx.get_char_size();
+        let scale = ((max[0] - min[0]) as u32 / width).max((max[1] - min[1]) as u32 / height) + 1;
+        let cell = |p: Pos| ((p[0] - min[0]) as u32 / scale, (max[1] - p[1]) as u32 / scale);
 
-        self.links[0].move_to(cmd);
-        self.links
-            .iter_mut()
-            .reduce(|front,tail|{
-                tail.move_relative(front);
-                tail
-            })
-            .unwrap()
-            .position()
+        ctx.cls();
+        self.rope.visited_cells(self.knots() - 1)
+            .map(cell)
+            .for_each(|(x, y)| ctx.set(x, y, GREY30, BLACK, to_cp437('\u{2592}')));
+        self.rope.knots().iter()
+            .enumerate()
+            .rev()
+            .for_each(|(i, &p)| {
+                let (x, y) = cell(p);
+                let (fg, glyph) = match i {
+                    0 => (RED, 'H'),
+                    _ if i == self.knots() - 1 => (YELLOW, 'T'),
+                    _ => (WHITE, char::from_digit(i as u32 % 10, 10).unwrap())
+                };
+                ctx.set(x, y, fg, BLACK, to_cp437(glyph));
+            });
     }
 }
 
-struct Game {
-    rope: Chain,
-    unique: HashSet<Coord>
-}
-impl Game {
-    fn new(rope: Chain) -> Game {
-        Game { rope, unique: HashSet::new() }
-    }
-    fn unique_positions(&self) -> usize {
-        self.unique.len()
-    }
-    fn run(&mut self, input: &Vec<Step>) -> &Self{
-        for step in input {
-            (0..step.units).all(|_| {
-                self.unique.insert(
-                    self.rope.move_to( step.cmd )
-                );
-                true
-            });
+struct Menu;
+impl AppLevel for Menu {
+    type GStore = Store;
+    type GLevel = Levels;
+
+    fn init(&mut self, ctx: &mut BTerm, _: &mut Self::GStore) -> (Self::GLevel, State) {
+        ctx.set_active_console(0);
+        ctx.cls();
+        (Levels::Menu, State::RUN)
+    }
+    fn run(&mut self, ctx: &mut BTerm, store: &mut Self::GStore) -> (Self::GLevel, State) {
+        ctx.set_active_console(1);
+        match ctx.key {
+            Some(VirtualKeyCode::Key1) => { ctx.cls(); store.reset(2); (Levels::Rope, State::INIT) },
+            Some(VirtualKeyCode::Key2) => { ctx.cls(); store.reset(10); (Levels::Rope, State::INIT) },
+            Some(VirtualKeyCode::Q) => (Levels::Menu, State::FINISH),
+            _ => {
+                ctx.print_centered(10, "MENU ");
+                ctx.print_centered(11, "==============================");
+                ctx.print_centered(13, "1. Rope with 2 knots");
+                ctx.print_centered(15, "2. Rope with 10 knots");
+                (Levels::Menu, State::RUN)
+            }
         }
-        self
+    }
+    fn term(&mut self, ctx: &mut BTerm, _: &mut Self::GStore) -> (Self::GLevel, State) {
+        ctx.quit();
+        (Levels::Menu, State::FINISH)
     }
 }
 
-fn parse_commands(input: &str) -> Vec<Step> {
-    input.lines()
-        .map(|line| line.split(' '))
-        .map(|mut s| {
-            let cmd = match s.next() {
-                Some("R") => Command::Right,
-                Some("U") => Command::Up,
-                Some("D") => Command::Down,
-                Some("L") => Command::Left,
-                _ => panic!("Woohaaaa!")
-            };
-            (cmd, isize::from_str(s.next().unwrap()).unwrap())
-        })
-        .fold(vec![], |mut out, (cmd, units)| {
-            out.push( Step{ cmd, units });
-            out
-        })
+/// Plays the head's moves `speed` per frame; `Space` pauses, `N` steps once while paused,
+/// `Up`/`Down` change speed, `T` switches between the 2 and 10 knot ropes, `R` restarts and `M` returns to the menu
+struct Animation {
+    paused: bool,
+    speed: usize
+}
+impl AppLevel for Animation {
+    type GStore = Store;
+    type GLevel = Levels;
+
+    fn init(&mut self, ctx: &mut BTerm, store: &mut Self::GStore) -> (Self::GLevel, State) {
+        self.paused = true;
+        self.speed = 1;
+        ctx.set_active_console(0);
+        store.draw(ctx);
+        (Levels::Rope, State::RUN)
+    }
+    fn run(&mut self, ctx: &mut BTerm, store: &mut Self::GStore) -> (Self::GLevel, State) {
+        match ctx.key {
+            Some(VirtualKeyCode::Space) => self.paused = !self.paused,
+            Some(VirtualKeyCode::N) if self.paused => { store.advance(); },
+            Some(VirtualKeyCode::Up) => self.speed = (self.speed * 2).min(1024),
+            Some(VirtualKeyCode::Down) => self.speed = (self.speed / 2).max(1),
+            Some(VirtualKeyCode::T) => store.switch(if store.knots() == 2 { 10 } else { 2 }),
+            Some(VirtualKeyCode::R) => store.reset(store.knots()),
+            Some(VirtualKeyCode::M) => return (Levels::Rope, State::FINISH),
+            _ => {}
+        }
+        if !self.paused && !(0..self.speed).all(|_| store.advance()) {
+            self.paused = true;
+        }
+
+        ctx.set_active_console(0);
+        store.draw(ctx);
 
+        ctx.set_active_console(1);
+        ctx.cls();
+        ctx.print(0, 0, format!("FPS: {}", ctx.fps));
+        ctx.print(1, 1, format!("Knots: {}  Move: {}/{}  Speed: {}/frame{}",
+            store.knots(), store.tick, store.moves.len(), self.speed, if self.paused { "  [paused]" } else { "" }));
+        ctx.print(1, 2, format!("Tail visited: {}", store.rope.visited(store.knots() - 1)));
+        ctx.print(1, 48, "Space: Pause, N: Step, Up/Down: Speed, T: 2/10 knots, R: Restart, M: Menu, Q: Quit");
+        (Levels::Rope, State::RUN)
+    }
+    fn term(&mut self, ctx: &mut BTerm, _: &mut Self::GStore) -> (Self::GLevel, State) {
+        ctx.set_active_console(1);
+        ctx.cls();
+        (Levels::Menu, State::INIT)
+    }
 }
 
-fn main() {
-//     let data = "R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2".to_string();
-//     let data = "R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20\n".to_string();
+#[cfg(test)]
+mod test {
+    use super::*;
+    use advent2022::rope::{follow, Point};
 
-    let data = std::fs::read_to_string("src/bin/day9_input.txt").expect("");
+    #[test]
+    fn test_sample_ropes() {
+        let steps = parse_steps("R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20").unwrap();
+        assert_eq!(Rope::new(Pos::default(), 10).run(&steps).visited(9), 36);
+        assert!(parse_steps::<2>("R 5\nX 1").is_err_and(|e| e.starts_with("line 2")));
+    }
+    #[test]
+    fn test_3d_rope() {
+        // planar moves give the same answers in space
+        let planar = "R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20";
+        let steps = parse_steps::<3>(planar).unwrap();
+        assert_eq!(Rope::new(Default::default(), 10).run(&steps).visited(9), 36);
 
-    let cmds = parse_commands(data.as_str());
+        let steps = parse_steps::<3>("F 3\n<1,-1,0> 2\nB 1").unwrap();
+        assert_eq!(steps[1].delta, (1, -1, 0).into());
+        let mut rope = Rope::new(Default::default(), 2).recording();
+        rope.run(&steps);
+        assert_eq!(rope.trail(1).unwrap(), &[
+            (0, 0, 0).into(), (0, 0, 0).into(), (0, 0, 1).into(), (0, 0, 2).into(),
+            (0, 0, 2).into(), (1, -1, 3).into(), (1, -1, 3).into()
+        ]);
+        assert!(rope.trace_csv().unwrap().starts_with("step,knot,x,y,z\n0,0,0,0,0\n"));
 
-    println!("2 Link Chain - Unique points: {}",
-             Game::new( Chain::new((0, 0).into(), 2))
-                 .run( &cmds )
-                 .unique_positions()
-    );
-    println!("10 Links Chain - Unique points: {}",
-             Game::new( Chain::new((0, 0).into(), 10))
-                 .run( &cmds )
-                 .unique_positions()
-    );
-}
\ No newline at end of file
+        assert!(parse_steps::<2>("F 1").is_err());
+        assert!(parse_steps::<3>("<1,0> 1").is_err());
+        assert_eq!(parse_steps::<4>("<0,0,0,1> 2").unwrap()[0].delta, Point([0, 0, 0, 1]));
+    }
+    #[test]
+    fn test_follow() {
+        let origin = Pos::default();
+        assert_eq!(follow(origin, (1, 1).into(), 1, |_| ()), origin);
+        assert_eq!(follow(origin, (2, 1).into(), 1, |_| ()), (1, 1).into());
+        assert_eq!(follow(origin, (-2, -2).into(), 1, |_| ()), (-1, -1).into());
+        let mut path = vec![];
+        assert_eq!(follow(origin, (5, -2).into(), 1, |p| path.push(p)), (4, -2).into());
+        assert_eq!(path, vec![(1, -1).into(), (2, -2).into(), (3, -2).into(), (4, -2).into()]);
+        assert_eq!(follow(origin, (3, 3).into(), 3, |_| ()), origin);
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use super::{Arg, AsmError, Instruction, InstructionSet, Word};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Symbol {
+    Const(Word),
+    Label(usize)
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Assemble a program, collecting every error instead of stopping at the first one
+fn assemble_all(set: &InstructionSet, src: &str) -> (Vec<Instruction>, Vec<AsmError>) {
+    let mut errors = vec![];
+    let mut symbols = HashMap::new();
+    let mut lines = vec![];
+
+    for (line, raw) in src.lines().enumerate().map(|(i, raw)| (i + 1, raw)) {
+        let mut text = raw.split(';').next().unwrap_or_default().trim();
+        let define = |symbols: &mut HashMap<String, Symbol>, name: &str, symbol: Symbol| {
+            (set.register_index(name).is_some() || symbols.insert(name.to_string(), symbol).is_some())
+                .then(|| AsmError::DuplicateSymbol { line, name: name.to_string() })
+        };
+        while let Some((label, rest)) = text.split_once(':').filter(|(label, _)| is_identifier(label.trim())) {
+            errors.extend(define(&mut symbols, label.trim(), Symbol::Label(lines.len())));
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue
+        }
+        match text.split_once('=') {
+            Some((name, value)) if is_identifier(name.trim()) => {
+                let value = value.trim();
+                let constant = value.parse::<Word>().ok().or_else(|| match symbols.get(value) {
+                    Some(&Symbol::Const(v)) => Some(v),
+                    _ => None
+                });
+                match constant {
+                    Some(v) => errors.extend(define(&mut symbols, name.trim(), Symbol::Const(v))),
+                    None => errors.push(AsmError::BadOperand { line, operand: value.to_string() })
+                }
+            }
+            _ => lines.push((line, text))
+        }
+    }
+
+    let program = lines.iter()
+        .enumerate()
+        .filter_map(|(addr, &(line, text))| {
+            let kinds = text.split_whitespace().next()
+                .and_then(|name| set.lookup(name))
+                .map_or(&[][..], |op| set.defs[op].args);
+            text.split_whitespace()
+                .enumerate()
+                .map(|(i, word)| match (symbols.get(word), i.checked_sub(1).and_then(|a| kinds.get(a))) {
+                    (Some(Symbol::Const(v)), Some(_)) => Ok(v.to_string()),
+                    (Some(&Symbol::Label(target)), Some(Arg::Target)) => Ok((target as Word - addr as Word).to_string()),
+                    (Some(Symbol::Label(_)), Some(_)) => Err(AsmError::MisplacedLabel { line, name: word.to_string() }),
+                    _ => Ok(word.to_string())
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|words| set.decode(line, &words.join(" ")))
+                .map_err(|e| errors.push(e))
+                .ok()
+        })
+        .collect();
+    (program, errors)
+}
+
+/// Assemble a program, reporting the first problem found; see `validate` for all of them.
+/// Each line holds an instruction, a constant `NAME = value` or nothing, optionally preceded by `label:`
+/// and followed by a `; comment`. Constants stand in for their value; labels for the offset from the
+/// instruction using them to the one they mark, and are only accepted as jump targets, e.g. by `jmp`
+/// ```
+/// use advent2022::emulator::{Cpu, InstructionSet, asm};
+///
+/// let set = InstructionSet::extended();
+/// let src = "STEP = 5     ; constants\n\
+///            start: addx STEP\n\
+///            jmp skip     ; labels are jump targets\n\
+///            addx 100\n\
+///            skip: mul x 2";
+/// let program = asm::assemble(&set, src).unwrap();
+/// assert_eq!(asm::disassemble(&set, &program),
+///            "addx 5              ; 0000: cycle 1..=2\n\
+///             jmp 2               ; 0001: cycle 3\n\
+///             addx 100            ; 0002: cycle 4..=5\n\
+///             mul x 2             ; 0003: cycle 6..=8\n");
+/// let mut cpu = Cpu::new(set);
+/// cpu.load(program);
+/// cpu.run();
+/// assert_eq!(cpu.reg("x"), Some(12));
+/// ```
+pub fn assemble(set: &InstructionSet, src: &str) -> Result<Vec<Instruction>, AsmError> {
+    let (program, errors) = assemble_all(set, src);
+    match errors.into_iter().min_by_key(AsmError::line) {
+        None => Ok(program),
+        Some(e) => Err(e)
+    }
+}
+
+/// Every problem in the source, in line order; unknown mnemonics, wrong operand counts,
+/// undefined or malformed operands, labels outside jump targets and symbols defined twice
+pub fn validate(set: &InstructionSet, src: &str) -> Vec<AsmError> {
+    let (_, mut errors) = assemble_all(set, src);
+    errors.sort_by_key(AsmError::line);
+    errors
+}
+
+/// One instruction per line, annotated with its address and the cycles it occupies when run straight through
+pub fn disassemble(set: &InstructionSet, program: &[Instruction]) -> String {
+    program.iter()
+        .enumerate()
+        .scan(1, |start, (addr, ins)| {
+            let cycles = set.def(ins).cycles;
+            let span = match cycles {
+                1 => format!("{start}"),
+                _ => format!("{start}..={}", *start + cycles - 1)
+            };
+            *start += cycles;
+            Some(format!("{:<20}; {addr:04}: cycle {span}\n", set.format(ins)))
+        })
+        .collect()
+}
@@ -0,0 +1,115 @@
+use std::ops::RangeInclusive;
+use crate::{Coord, Grid};
+use super::{Cycles, Word};
+
+/// Width and height of a block letter
+pub const GLYPH: (usize, usize) = (4, 6);
+const FONT: [(char, [&str; 6]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+/// Read the 4x6 block letters off a screen, left to right with one blank column between letters;
+/// every 6 rows make a line of text. Unknown glyphs read as `?`, blank ones as spaces
+/// ```
+/// use advent2022::emulator::crt::Crt;
+///
+/// let mut crt = Crt::new(10, 6, 1);
+/// let hi = ["#..#..###.", "#..#...#..", "####...#..", "#..#...#..", "#..#...#..", "#..#..###."];
+/// hi.iter().flat_map(|row| row.bytes()).enumerate()
+///     .for_each(|(i, b)| { crt.tick(if b == b'#' { (i % 10) as isize } else { -5 }); });
+/// assert_eq!(crt.ocr(), "HI");
+/// ```
+pub fn ocr(screen: &Grid<bool>) -> String {
+    let (width, height) = GLYPH;
+    let glyph_mask = |x0: usize, y0: usize| (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .fold(0u32, |mask, (x, y)| {
+            mask << 1 | *screen.square(Coord { x: x0 + x, y: y0 + y }).unwrap_or(&false) as u32
+        });
+    let font = FONT.iter()
+        .map(|(c, rows)| (rows.concat().bytes().fold(0u32, |mask, b| mask << 1 | (b == b'#') as u32), *c))
+        .collect::<Vec<_>>();
+
+    (0..screen.height / height)
+        .map(|line| {
+            (0..screen.width.div_ceil(width + 1))
+                .map(|i| match glyph_mask(i * (width + 1), line * height) {
+                    0 => ' ',
+                    mask => font.iter().find(|&&(m, _)| m == mask).map_or('?', |&(_, c)| c)
+                })
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Cathode ray tube drawing one pixel per cycle into a framebuffer, row by row; the pixel lights up
+/// when the beam falls within the sprite, a `sprite` pixels wide window centred on register X
+pub struct Crt {
+    pub screen: Grid<bool>,
+    sprite: usize,
+    clock: Cycles
+}
+impl Crt {
+    pub fn new(width: usize, height: usize, sprite: usize) -> Crt {
+        Crt { screen: Grid::new(width, height), sprite, clock: 0 }
+    }
+    /// The puzzle's 40x6 screen with a 3 pixel sprite
+    pub fn day10() -> Crt {
+        Crt::new(40, 6, 3)
+    }
+    /// Where the next pixel will be drawn; wraps around to the top left after a full frame
+    pub fn beam(&self) -> Coord {
+        let pos = self.clock % (self.screen.width * self.screen.height);
+        Coord { x: pos % self.screen.width, y: pos / self.screen.width }
+    }
+    /// Columns covered by the sprite for the given X
+    pub fn sprite(&self, x: Word) -> RangeInclusive<Word> {
+        let w = self.sprite as Word;
+        x - (w - 1) / 2 ..= x + w / 2
+    }
+    /// Draw the pixel under the beam and advance; returns where it was drawn and if it lit up
+    pub fn tick(&mut self, x: Word) -> (Coord, bool) {
+        let beam = self.beam();
+        let lit = self.sprite(x).contains(&(beam.x as Word));
+        *self.screen.square_mut(beam).unwrap() = lit;
+        self.clock += 1;
+        (beam, lit)
+    }
+    /// Blank the screen and move the beam back to the top left
+    pub fn clear(&mut self) {
+        self.screen.iter_mut().for_each(|p| *p = false);
+        self.clock = 0;
+    }
+    pub fn clock(&self) -> Cycles {
+        self.clock
+    }
+    /// Screen as `#` and `.` rows
+    pub fn text(&self) -> String {
+        self.screen.grid
+            .chunks(self.screen.width)
+            .map(|row| row.iter().map(|&p| if p { '#' } else { '.' }).collect::<String>() + "\n")
+            .collect()
+    }
+    pub fn ocr(&self) -> String {
+        ocr(&self.screen)
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use super::{Cpu, Cycles, Instruction, InstructionSet, Status, Word};
+
+/// CPU state during one cycle; the instruction in flight, the cycles it still needs and every register
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    pub cycle: Cycles,
+    pub addr: Option<usize>,
+    pub instruction: Option<String>,
+    pub remaining: Cycles,
+    pub regs: Vec<(String, Word)>
+}
+impl Trace {
+    pub fn capture(cpu: &Cpu) -> Trace {
+        let set = cpu.instruction_set();
+        let busy = cpu.busy();
+        Trace {
+            cycle: cpu.cycle(),
+            addr: busy.map(|b| b.addr),
+            instruction: cpu.current().map(|ins| set.format(ins)),
+            remaining: busy.map_or(0, |b| b.remaining),
+            regs: set.register_names()
+                .zip(&cpu.machine().regs)
+                .map(|(n, &v)| (n.to_string(), v))
+                .collect()
+        }
+    }
+    /// Same instruction and register values; addresses may differ
+    fn agrees(&self, other: &Trace) -> bool {
+        self.instruction == other.instruction && self.regs == other.regs
+    }
+}
+impl Display for Trace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (self.addr, &self.instruction) {
+            (Some(addr), Some(ins)) => write!(f, "{:>6} {addr:>4}: {ins:<16} +{}", self.cycle, self.remaining)?,
+            _ => write!(f, "{:>6}       {:<16}   ", self.cycle, "-")?
+        }
+        self.regs.iter().try_for_each(|(n, v)| write!(f, " {n}={v}"))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Cmp { Eq, Ne, Lt, Le, Gt, Ge }
+impl Cmp {
+    const SYMBOLS: [(&'static str, Cmp); 6] = [
+        ("==", Cmp::Eq), ("!=", Cmp::Ne), ("<=", Cmp::Le), (">=", Cmp::Ge), ("<", Cmp::Lt), (">", Cmp::Gt)
+    ];
+    pub fn holds(self, a: Word, b: Word) -> bool {
+        match self {
+            Cmp::Eq => a == b,
+            Cmp::Ne => a != b,
+            Cmp::Lt => a < b,
+            Cmp::Le => a <= b,
+            Cmp::Gt => a > b,
+            Cmp::Ge => a >= b
+        }
+    }
+}
+
+/// Where to stop; at a given cycle, e.g. `@20`, or once a register condition becomes true, e.g. `x >= 10`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    Cycle(Cycles),
+    Reg { name: String, cmp: Cmp, value: Word }
+}
+impl Breakpoint {
+    pub fn holds(&self, cpu: &Cpu) -> bool {
+        match self {
+            Breakpoint::Cycle(c) => cpu.cycle() == *c,
+            Breakpoint::Reg { name, cmp, value } => cpu.reg(name).is_some_and(|v| cmp.holds(v, *value))
+        }
+    }
+}
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(cycle) = s.strip_prefix('@') {
+            return cycle.trim().parse().map(Breakpoint::Cycle).map_err(|e| format!("'{s}': {e}"))
+        }
+        let (pos, sym, cmp) = Cmp::SYMBOLS.iter()
+            .filter_map(|&(sym, cmp)| s.find(sym).map(|pos| (pos, sym, cmp)))
+            .min_by_key(|&(pos, sym, _)| (pos, std::cmp::Reverse(sym.len())))
+            .ok_or(format!("'{s}': expected '@<cycle>' or '<register> <op> <value>'"))?;
+        let name = s[..pos].trim();
+        if name.is_empty() {
+            return Err(format!("'{s}': missing register name"))
+        }
+        Ok(Breakpoint::Reg {
+            name: name.to_string(),
+            cmp,
+            value: s[pos + sym.len()..].trim().parse().map_err(|e| format!("'{s}': {e}"))?
+        })
+    }
+}
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::Cycle(c) => write!(f, "@{c}"),
+            Breakpoint::Reg { name, cmp, value } => {
+                let (sym, _) = Cmp::SYMBOLS.iter().find(|(_, c)| c == cmp).unwrap();
+                write!(f, "{name} {sym} {value}")
+            }
+        }
+    }
+}
+
+/// Why the debugger handed back control
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stop {
+    Step,
+    Breakpoint(usize),
+    Halted
+}
+
+/// Drives a `Cpu` one cycle at a time, stopping at breakpoints and optionally logging a trace per cycle.
+/// Register breakpoints fire on the cycle their condition turns true, so resuming moves past them
+/// ```
+/// use advent2022::emulator::{Cpu, InstructionSet, debug::{Debugger, Stop}};
+///
+/// let set = InstructionSet::day10();
+/// let mut cpu = Cpu::new(set.clone());
+/// cpu.load(set.parse("noop\naddx 3\naddx -5\nnoop").unwrap());
+///
+/// let mut dbg = Debugger::new(cpu).tracing();
+/// dbg.add_breakpoint("x < 0".parse().unwrap());
+/// dbg.add_breakpoint("@2".parse().unwrap());
+/// assert_eq!(dbg.resume(), Stop::Breakpoint(1));
+/// assert_eq!(dbg.step(), Stop::Step);
+/// assert_eq!(dbg.resume(), Stop::Breakpoint(0));
+/// assert_eq!(dbg.cpu().cycle(), 6);
+/// assert_eq!(dbg.resume(), Stop::Halted);
+/// assert_eq!(dbg.trace().len(), 6);
+/// ```
+pub struct Debugger {
+    cpu: Cpu,
+    breakpoints: Vec<(Breakpoint, bool)>,
+    trace: Option<Vec<Trace>>
+}
+impl Debugger {
+    pub fn new(cpu: Cpu) -> Debugger {
+        Debugger { cpu, breakpoints: vec![], trace: None }
+    }
+    /// Log a `Trace` of every cycle run from now on
+    pub fn tracing(mut self) -> Debugger {
+        self.trace = Some(vec![]);
+        self
+    }
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+    pub fn add_breakpoint(&mut self, bp: Breakpoint) -> usize {
+        let holds = bp.holds(&self.cpu);
+        self.breakpoints.push((bp, holds));
+        self.breakpoints.len() - 1
+    }
+    pub fn remove_breakpoint(&mut self, index: usize) -> Option<Breakpoint> {
+        (index < self.breakpoints.len()).then(|| self.breakpoints.remove(index).0)
+    }
+    pub fn breakpoints(&self) -> impl Iterator<Item=&Breakpoint> {
+        self.breakpoints.iter().map(|(bp, _)| bp)
+    }
+    /// Run a single cycle
+    pub fn step(&mut self) -> Stop {
+        if self.cpu.tick() == Status::Halted {
+            return Stop::Halted
+        }
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(Trace::capture(&self.cpu));
+        }
+        let cpu = &self.cpu;
+        self.breakpoints.iter_mut()
+            .enumerate()
+            .fold(None, |hit, (i, (bp, held))| {
+                let holds = bp.holds(cpu);
+                let fired = holds && !*held;
+                *held = holds;
+                hit.or(fired.then_some(i))
+            })
+            .map_or(Stop::Step, Stop::Breakpoint)
+    }
+    /// Run until a breakpoint fires or the program halts
+    pub fn resume(&mut self) -> Stop {
+        loop {
+            match self.step() {
+                Stop::Step => continue,
+                stop => return stop
+            }
+        }
+    }
+    pub fn trace(&self) -> &[Trace] {
+        self.trace.as_deref().unwrap_or_default()
+    }
+}
+
+/// Run a program for at most `max_cycles` cycles and return its trace, along with whether it halted
+/// by then; programs that loop forever come back with a trace cut short
+/// ```
+/// use advent2022::emulator::{InstructionSet, debug};
+///
+/// let set = InstructionSet::extended();
+/// let (trace, halted) = debug::trace(&set, set.parse("noop\njmp 0").unwrap(), 100);
+/// assert_eq!((trace.len(), halted), (100, false));
+/// let (trace, halted) = debug::trace(&set, set.parse("noop\naddx 2").unwrap(), 3);
+/// assert_eq!((trace.len(), halted), (3, true));
+/// ```
+pub fn trace(set: &InstructionSet, program: Vec<Instruction>, max_cycles: Cycles) -> (Vec<Trace>, bool) {
+    let mut cpu = Cpu::new(set.clone());
+    cpu.load(program);
+    let mut dbg = Debugger::new(cpu).tracing();
+    // halting does not use up a cycle, so one more step tells if the program ends right at the limit
+    let halted = (0..=max_cycles).any(|_| dbg.step() == Stop::Halted);
+    let mut trace = dbg.trace.unwrap_or_default();
+    trace.truncate(max_cycles);
+    (trace, halted)
+}
+
+/// Cycles at which two traces disagree on the instruction in flight or any register value;
+/// once the shorter trace ends, every further cycle of the longer one is a difference
+pub fn diff<'a>(a: &'a [Trace], b: &'a [Trace]) -> Vec<(Cycles, Option<&'a Trace>, Option<&'a Trace>)> {
+    (0..a.len().max(b.len()))
+        .map(|i| (i + 1, a.get(i), b.get(i)))
+        .filter(|&(_, ta, tb)| match (ta, tb) {
+            (Some(ta), Some(tb)) => !ta.agrees(tb),
+            _ => true
+        })
+        .collect()
+}
@@ -0,0 +1,320 @@
+pub mod asm;
+pub mod crt;
+pub mod debug;
+
+use std::fmt::{Display, Formatter};
+
+pub type Word = isize;
+pub type Cycles = usize;
+
+/// Kind of operand an instruction takes
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Arg {
+    /// A register name; the instruction may write to it
+    Reg,
+    /// A register name or an immediate value; read only
+    Value,
+    /// A jump offset, relative to the instruction; read like a value, and the only place labels are accepted
+    Target
+}
+
+/// A decoded operand; registers are held by their index in the register file
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Reg(usize),
+    Imm(Word)
+}
+
+/// What an instruction does to the machine once its cycles are used up
+pub type Effect = fn(&mut Machine, &[Operand]);
+
+/// One row of the instruction table
+#[derive(Clone)]
+pub struct InstructionDef {
+    pub name: &'static str,
+    pub args: &'static [Arg],
+    pub cycles: Cycles,
+    pub effect: Effect
+}
+impl InstructionDef {
+    pub fn arity(&self) -> usize {
+        self.args.len()
+    }
+}
+
+/// A decoded instruction; `op` indexes the instruction table it was decoded against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub op: usize,
+    pub args: Vec<Operand>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmError {
+    UnknownMnemonic { line: usize, name: String },
+    Arity { line: usize, name: String, expected: usize, got: usize },
+    BadOperand { line: usize, operand: String },
+    DuplicateSymbol { line: usize, name: String },
+    MisplacedLabel { line: usize, name: String }
+}
+impl AsmError {
+    pub fn line(&self) -> usize {
+        match self {
+            AsmError::UnknownMnemonic { line, .. } | AsmError::Arity { line, .. }
+            | AsmError::BadOperand { line, .. } | AsmError::DuplicateSymbol { line, .. }
+            | AsmError::MisplacedLabel { line, .. } => *line
+        }
+    }
+}
+impl Display for AsmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AsmError::UnknownMnemonic { line, name } => write!(f, "line {line}: unknown mnemonic '{name}'"),
+            AsmError::Arity { line, name, expected, got } => write!(f, "line {line}: '{name}' takes {expected} operands, got {got}"),
+            AsmError::BadOperand { line, operand } => write!(f, "line {line}: invalid operand '{operand}'"),
+            AsmError::DuplicateSymbol { line, name } => write!(f, "line {line}: '{name}' is already defined"),
+            AsmError::MisplacedLabel { line, name } => write!(f, "line {line}: label '{name}' used where a jump target is not expected"),
+        }
+    }
+}
+impl std::error::Error for AsmError {}
+
+/// The instruction table together with the named registers and their initial values
+/// ```
+/// use advent2022::emulator::{Arg, Cpu, InstructionSet, Machine, Operand};
+///
+/// let set = InstructionSet::day10()
+///     .define("halve", &[Arg::Reg], 1, |m: &mut Machine, a: &[Operand]| m.set(a[0], m.get(a[0]) / 2));
+/// let program = set.parse("addx 5\nhalve x\nnoop").unwrap();
+/// let mut cpu = Cpu::new(set);
+/// cpu.load(program);
+/// cpu.run();
+/// assert_eq!((cpu.cycle(), cpu.reg("x")), (4, Some(3)));
+/// assert!(cpu.is_halted());
+/// ```
+#[derive(Clone, Default)]
+pub struct InstructionSet {
+    defs: Vec<InstructionDef>,
+    registers: Vec<(String, Word)>
+}
+impl InstructionSet {
+    /// The puzzle's machine; `noop` and `addx V` over register `x`, starting at 1
+    pub fn day10() -> InstructionSet {
+        InstructionSet::default()
+            .register("x", 1)
+            .define("noop", &[], 1, |_, _| {})
+            .define("addx", &[Arg::Value], 2, |m, a| m.add(m.index("x"), m.get(a[0])))
+    }
+    /// The puzzle's machine plus register `y`, `addy V`, `mul R V` and the relative jump `jmp V`
+    pub fn extended() -> InstructionSet {
+        InstructionSet::day10()
+            .register("y", 0)
+            .define("addy", &[Arg::Value], 2, |m, a| m.add(m.index("y"), m.get(a[0])))
+            .define("mul", &[Arg::Reg, Arg::Value], 3, |m, a| m.set(a[0], m.get(a[0]) * m.get(a[1])))
+            .define("jmp", &[Arg::Target], 1, |m, a| m.jump(m.get(a[0])))
+    }
+    /// Add a register, or reset the initial value of an existing one
+    pub fn register(mut self, name: &str, initial: Word) -> InstructionSet {
+        match self.registers.iter_mut().find(|(n, _)| n == name) {
+            Some(reg) => reg.1 = initial,
+            None => self.registers.push((name.to_string(), initial))
+        }
+        self
+    }
+    /// Add an instruction, or replace an existing one with the same name
+    pub fn define(mut self, name: &'static str, args: &'static [Arg], cycles: Cycles, effect: Effect) -> InstructionSet {
+        assert!(cycles > 0, "{name}: instructions take at least one cycle");
+        let def = InstructionDef { name, args, cycles, effect };
+        match self.defs.iter_mut().find(|d| d.name == name) {
+            Some(d) => *d = def,
+            None => self.defs.push(def)
+        }
+        self
+    }
+    pub fn lookup(&self, name: &str) -> Option<usize> {
+        self.defs.iter().position(|d| d.name == name)
+    }
+    pub fn def(&self, ins: &Instruction) -> &InstructionDef {
+        &self.defs[ins.op]
+    }
+    pub fn register_index(&self, name: &str) -> Option<usize> {
+        self.registers.iter().position(|(n, _)| n == name)
+    }
+    pub fn register_names(&self) -> impl Iterator<Item=&str> {
+        self.registers.iter().map(|(n, _)| n.as_str())
+    }
+    /// Decode one line, e.g. `addx -3`; `line` is only used for error reporting
+    pub fn decode(&self, line: usize, text: &str) -> Result<Instruction, AsmError> {
+        let mut words = text.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let op = self.lookup(name).ok_or(AsmError::UnknownMnemonic { line, name: name.to_string() })?;
+        let words = words.collect::<Vec<_>>();
+        let def = &self.defs[op];
+        if words.len() != def.arity() {
+            return Err(AsmError::Arity { line, name: name.to_string(), expected: def.arity(), got: words.len() })
+        }
+        let args = words.iter().zip(def.args)
+            .map(|(&word, arg)| match (self.register_index(word), word.parse::<Word>()) {
+                (Some(r), _) => Ok(Operand::Reg(r)),
+                (None, Ok(v)) if *arg != Arg::Reg => Ok(Operand::Imm(v)),
+                _ => Err(AsmError::BadOperand { line, operand: word.to_string() })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Instruction { op, args })
+    }
+    /// Decode a program, one instruction per line, skipping blank lines
+    pub fn parse(&self, src: &str) -> Result<Vec<Instruction>, AsmError> {
+        src.lines()
+            .enumerate()
+            .filter(|(_, text)| !text.trim().is_empty())
+            .map(|(i, text)| self.decode(i + 1, text))
+            .collect()
+    }
+    /// Instruction as source text, e.g. `addx -3`
+    pub fn format(&self, ins: &Instruction) -> String {
+        ins.args.iter()
+            .fold(self.def(ins).name.to_string(), |mut out, arg| {
+                match arg {
+                    Operand::Reg(r) => out.push_str(&format!(" {}", self.registers[*r].0)),
+                    Operand::Imm(v) => out.push_str(&format!(" {v}"))
+                }
+                out
+            })
+    }
+}
+
+/// Register file and program counter, as seen by instruction effects;
+/// while an effect runs `pc` already points past the instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Machine {
+    names: Vec<String>,
+    pub regs: Vec<Word>,
+    pub pc: usize
+}
+impl Machine {
+    pub fn index(&self, name: &str) -> Operand {
+        Operand::Reg(self.names.iter().position(|n| n == name).unwrap_or_else(|| panic!("no register '{name}'")))
+    }
+    pub fn get(&self, op: Operand) -> Word {
+        match op {
+            Operand::Reg(r) => self.regs[r],
+            Operand::Imm(v) => v
+        }
+    }
+    /// Write a register; writes to immediates are ignored
+    pub fn set(&mut self, op: Operand, value: Word) {
+        if let Operand::Reg(r) = op {
+            self.regs[r] = value
+        }
+    }
+    pub fn add(&mut self, op: Operand, value: Word) {
+        self.set(op, self.get(op) + value)
+    }
+    /// Continue at `offset` instructions from the current one; jumping off either end halts the program
+    pub fn jump(&mut self, offset: Word) {
+        self.pc = (self.pc - 1).wrapping_add_signed(offset)
+    }
+    pub fn reg(&self, name: &str) -> Option<Word> {
+        self.names.iter().position(|n| n == name).map(|r| self.regs[r])
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Status { Running, Halted }
+
+/// The instruction in flight; where it was fetched from and the cycles it still needs
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Busy {
+    pub addr: usize,
+    pub remaining: Cycles
+}
+
+/// Fetch/execute loop; an instruction takes effect at the start of the cycle following its last one,
+/// so registers read after `tick` hold their value *during* that cycle
+pub struct Cpu {
+    set: InstructionSet,
+    program: Vec<Instruction>,
+    machine: Machine,
+    busy: Option<Busy>,
+    cycle: Cycles,
+    status: Status
+}
+impl Cpu {
+    pub fn new(set: InstructionSet) -> Cpu {
+        let machine = Machine {
+            names: set.registers.iter().map(|(n, _)| n.clone()).collect(),
+            regs: set.registers.iter().map(|&(_, v)| v).collect(),
+            pc: 0
+        };
+        Cpu { set, program: vec![], machine, busy: None, cycle: 0, status: Status::Running }
+    }
+    pub fn load(&mut self, program: Vec<Instruction>) {
+        self.program = program;
+        self.reset();
+    }
+    /// Back to cycle 0 with the initial register values, keeping the program
+    pub fn reset(&mut self) {
+        self.machine.regs = self.set.registers.iter().map(|&(_, v)| v).collect();
+        self.machine.pc = 0;
+        self.busy = None;
+        self.cycle = 0;
+        self.status = Status::Running;
+    }
+    /// Run one cycle; retire the instruction in flight if it is done, fetch the next one if idle.
+    /// Halts, without using up a cycle, once there is nothing left to fetch
+    pub fn tick(&mut self) -> Status {
+        if self.status == Status::Halted {
+            return Status::Halted
+        }
+        if let Some(Busy { addr, remaining: 0 }) = self.busy {
+            let ins = &self.program[addr];
+            (self.set.defs[ins.op].effect)(&mut self.machine, &ins.args);
+            self.busy = None;
+        }
+        if self.busy.is_none() {
+            let addr = self.machine.pc;
+            let Some(ins) = self.program.get(addr) else {
+                self.status = Status::Halted;
+                return Status::Halted
+            };
+            self.busy = Some(Busy { addr, remaining: self.set.defs[ins.op].cycles });
+            self.machine.pc += 1;
+        }
+        if let Some(busy) = self.busy.as_mut() {
+            busy.remaining -= 1;
+        }
+        self.cycle += 1;
+        Status::Running
+    }
+    /// Tick until halted; returns the number of cycles run
+    pub fn run(&mut self) -> Cycles {
+        let start = self.cycle;
+        while self.tick() == Status::Running {}
+        self.cycle - start
+    }
+    pub fn cycle(&self) -> Cycles {
+        self.cycle
+    }
+    pub fn is_halted(&self) -> bool {
+        self.status == Status::Halted
+    }
+    pub fn reg(&self, name: &str) -> Option<Word> {
+        self.machine.reg(name)
+    }
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+    pub fn busy(&self) -> Option<Busy> {
+        self.busy
+    }
+    /// The instruction in flight
+    pub fn current(&self) -> Option<&Instruction> {
+        self.busy.map(|b| &self.program[b.addr])
+    }
+    pub fn program(&self) -> &[Instruction] {
+        &self.program
+    }
+    pub fn instruction_set(&self) -> &InstructionSet {
+        &self.set
+    }
+}
@@ -0,0 +1,189 @@
+use std::ops::RangeInclusive;
+use std::slice::Iter;
+
+/// Values with a well defined successor and predecessor, so that `1..=3` and `4..=6` can be told apart from `1..=3` and `5..=6`
+pub trait Discrete: Copy + Ord {
+    fn succ(self) -> Option<Self>;
+    fn pred(self) -> Option<Self>;
+}
+macro_rules! discrete_impl {
+    ($($t:ty)*) => {$(
+        impl Discrete for $t {
+            fn succ(self) -> Option<Self> { self.checked_add(1) }
+            fn pred(self) -> Option<Self> { self.checked_sub(1) }
+        }
+    )*}
+}
+discrete_impl! { u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize }
+
+/// Interval algebra over inclusive ranges
+/// ```
+/// use advent2022::interval::Interval;
+///
+/// assert!((2..=8).contains_range(&(3..=7)));
+/// assert!((3..=7).is_subset(&(2..=8)));
+/// assert!((5..=7).overlaps(&(7..=9)));
+/// assert_eq!((5..=7).intersection(&(6..=9)), Some(6..=7));
+/// assert_eq!((5..=7).union(&(8..=9)), Some(5..=9));
+/// assert_eq!((5..=7).union(&(9..=9)), None);
+/// assert_eq!((1..=9).difference(&(4..=5)).ranges(), &[1..=3, 6..=9]);
+/// ```
+pub trait Interval<T>: Sized {
+    /// Both ranges share at least one value
+    fn overlaps(&self, other: &Self) -> bool;
+    /// The other range is fully enclosed by this range
+    fn contains_range(&self, other: &Self) -> bool;
+    /// This range is fully enclosed by the other range
+    fn is_subset(&self, other: &Self) -> bool {
+        other.contains_range(self)
+    }
+    /// One range starts right after the other ends
+    fn is_adjacent(&self, other: &Self) -> bool;
+    fn intersection(&self, other: &Self) -> Option<Self>;
+    /// Single range covering both, if they overlap or touch
+    fn union(&self, other: &Self) -> Option<Self>;
+    fn difference(&self, other: &Self) -> IntervalSet<T>;
+}
+impl<T: Discrete> Interval<T> for RangeInclusive<T> {
+    fn overlaps(&self, other: &Self) -> bool {
+        !self.is_empty() && !other.is_empty()
+            && self.start() <= other.end() && other.start() <= self.end()
+    }
+    fn contains_range(&self, other: &Self) -> bool {
+        !other.is_empty() && self.contains(other.start()) && self.contains(other.end())
+    }
+    fn is_adjacent(&self, other: &Self) -> bool {
+        !self.is_empty() && !other.is_empty() && (
+            self.end().succ() == Some(*other.start()) || other.end().succ() == Some(*self.start())
+        )
+    }
+    fn intersection(&self, other: &Self) -> Option<Self> {
+        self.overlaps(other)
+            .then(|| *self.start().max(other.start()) ..= *self.end().min(other.end()))
+    }
+    fn union(&self, other: &Self) -> Option<Self> {
+        (self.overlaps(other) || self.is_adjacent(other))
+            .then(|| *self.start().min(other.start()) ..= *self.end().max(other.end()))
+    }
+    fn difference(&self, other: &Self) -> IntervalSet<T> {
+        let mut set = IntervalSet::from_iter([self.clone()]);
+        set.remove(other.clone());
+        set
+    }
+}
+
+/// Set of values held as sorted, non-overlapping and non-adjacent inclusive ranges
+/// ```
+/// use advent2022::interval::IntervalSet;
+///
+/// let mut set = IntervalSet::from_iter([12..=12, 2..=10, 11..=11, -2..=2, 16..=24, 14..=18]);
+/// assert_eq!(set.ranges(), &[-2..=12, 14..=24]);
+/// assert_eq!(set.gaps().collect::<Vec<_>>(), vec![13..=13]);
+/// set.remove(0..=15);
+/// assert_eq!(set.ranges(), &[-2..=-1, 16..=24]);
+/// assert!(set.contains(&20) && !set.contains(&12));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntervalSet<T> {
+    ranges: Vec<RangeInclusive<T>>
+}
+impl<T> Default for IntervalSet<T> {
+    fn default() -> Self {
+        IntervalSet { ranges: Vec::new() }
+    }
+}
+impl<T: Discrete> IntervalSet<T> {
+    pub fn new() -> IntervalSet<T> {
+        IntervalSet::default()
+    }
+    pub fn ranges(&self) -> &[RangeInclusive<T>] {
+        &self.ranges
+    }
+    pub fn iter(&self) -> Iter<'_, RangeInclusive<T>> {
+        self.ranges.iter()
+    }
+    /// Number of disjoint ranges held
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+    pub fn contains(&self, val: &T) -> bool {
+        let idx = self.ranges.partition_point(|r| r.end() < val);
+        self.ranges.get(idx).is_some_and(|r| r.contains(val))
+    }
+    /// Adds the range, merging it with any range it overlaps or touches
+    pub fn insert(&mut self, range: RangeInclusive<T>) {
+        if range.is_empty() { return }
+        let (start, end) = range.into_inner();
+
+        // ranges ending before `start` and not touching it stay as is
+        let lo = self.ranges.partition_point(|r| r.end().succ().is_some_and(|e| e < start));
+        // ranges starting after `end` and not touching it stay as is
+        let hi = self.ranges.partition_point(|r| end.succ().is_none_or(|e| *r.start() <= e));
+
+        let merged = if lo < hi {
+            start.min(*self.ranges[lo].start()) ..= end.max(*self.ranges[hi-1].end())
+        } else {
+            start..=end
+        };
+        self.ranges.splice(lo..hi, [merged]);
+    }
+    /// Takes the range out of the set, splitting any range that encloses it
+    pub fn remove(&mut self, range: RangeInclusive<T>) {
+        if range.is_empty() { return }
+        let (start, end) = range.into_inner();
+
+        let lo = self.ranges.partition_point(|r| *r.end() < start);
+        let hi = self.ranges.partition_point(|r| *r.start() <= end);
+
+        let remain = self.ranges[lo..hi].iter()
+            .flat_map(|r| [
+                start.pred().filter(|p| r.start() <= p).map(|p| *r.start()..=p),
+                end.succ().filter(|s| s <= r.end()).map(|s| s..=*r.end())
+            ])
+            .flatten()
+            .collect::<Vec<_>>();
+        self.ranges.splice(lo..hi, remain);
+    }
+    pub fn union(&self, other: &Self) -> IntervalSet<T> {
+        other.iter().cloned().fold(self.clone(), |mut set, r| { set.insert(r); set })
+    }
+    pub fn difference(&self, other: &Self) -> IntervalSet<T> {
+        other.iter().cloned().fold(self.clone(), |mut set, r| { set.remove(r); set })
+    }
+    pub fn intersection(&self, other: &Self) -> IntervalSet<T> {
+        let (mut a, mut b) = (self.ranges.iter().peekable(), other.ranges.iter().peekable());
+        let mut ranges = vec![];
+        while let (Some(&x), Some(&y)) = (a.peek(), b.peek()) {
+            if let Some(r) = x.intersection(y) {
+                ranges.push(r);
+            }
+            if x.end() < y.end() { a.next(); } else { b.next(); }
+        }
+        IntervalSet { ranges }
+    }
+    /// Ranges of values missing between the lowest and the highest value held
+    pub fn gaps(&self) -> impl Iterator<Item=RangeInclusive<T>> + '_ {
+        self.ranges.windows(2)
+            .filter_map(|w| Some(w[0].end().succ()? ..= w[1].start().pred()?))
+    }
+    /// Ranges of values within `bounds` that are not held by the set
+    pub fn gaps_within(&self, bounds: RangeInclusive<T>) -> IntervalSet<T> {
+        IntervalSet::from_iter([bounds]).difference(self)
+    }
+}
+impl<T: Discrete> FromIterator<RangeInclusive<T>> for IntervalSet<T> {
+    fn from_iter<I: IntoIterator<Item=RangeInclusive<T>>>(iter: I) -> Self {
+        iter.into_iter().fold(IntervalSet::new(), |mut set, r| { set.insert(r); set })
+    }
+}
+impl<T> IntoIterator for IntervalSet<T> {
+    type Item = RangeInclusive<T>;
+    type IntoIter = std::vec::IntoIter<RangeInclusive<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ranges.into_iter()
+    }
+}
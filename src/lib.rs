@@ -4,6 +4,10 @@ use std::slice::{Iter, IterMut};
 use std::str::FromStr;
 
 pub mod app;
+pub mod emulator;
+pub mod interval;
+pub mod rope;
+pub mod rucksack;
 
 #[derive(Ord, PartialOrd,Copy, Clone, Eq, PartialEq, Hash)]
 pub struct Coord {
@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+use std::fmt::{Debug, Formatter};
+use std::ops::{Add, AddAssign, Index, Sub};
+use std::str::FromStr;
+
+/// Knot position on the unbounded `N` dimensional integer grid
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Point<const N: usize>(pub [isize; N]);
+/// Knot position on the plane, as in the puzzle
+pub type Pos = Point<2>;
+
+impl<const N: usize> Point<N> {
+    /// Unit vector along `axis`, pointing the way of `sign`
+    pub fn unit(axis: usize, sign: isize) -> Point<N> {
+        let mut p = Point::default();
+        p.0[axis] = sign.signum();
+        p
+    }
+    /// Largest distance along any one axis; knots touch when it is at most 1
+    pub fn chebyshev(self, other: Point<N>) -> isize {
+        (self - other).0.iter().map(|d| d.abs()).max().unwrap_or(0)
+    }
+    /// Unit vector pointing the same way along every axis
+    pub fn signum(self) -> Point<N> {
+        Point(self.0.map(isize::signum))
+    }
+}
+impl<const N: usize> Default for Point<N> {
+    fn default() -> Self {
+        Point([0; N])
+    }
+}
+impl<const N: usize> Debug for Point<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({})", self.0.map(|v| v.to_string()).join(","))
+    }
+}
+impl<const N: usize> From<[isize; N]> for Point<N> {
+    fn from(p: [isize; N]) -> Self {
+        Point(p)
+    }
+}
+impl From<(isize,isize)> for Point<2> {
+    fn from(p: (isize, isize)) -> Self {
+        Point([p.0, p.1])
+    }
+}
+impl From<(isize,isize,isize)> for Point<3> {
+    fn from(p: (isize, isize, isize)) -> Self {
+        Point([p.0, p.1, p.2])
+    }
+}
+impl<const N: usize> Index<usize> for Point<N> {
+    type Output = isize;
+    fn index(&self, axis: usize) -> &isize {
+        &self.0[axis]
+    }
+}
+impl<const N: usize> Add for Point<N> {
+    type Output = Point<N>;
+    fn add(self, rhs: Point<N>) -> Point<N> {
+        Point(std::array::from_fn(|i| self.0[i] + rhs.0[i]))
+    }
+}
+impl<const N: usize> AddAssign for Point<N> {
+    fn add_assign(&mut self, rhs: Point<N>) {
+        *self = *self + rhs
+    }
+}
+impl<const N: usize> Sub for Point<N> {
+    type Output = Point<N>;
+    fn sub(self, rhs: Point<N>) -> Point<N> {
+        Point(std::array::from_fn(|i| self.0[i] - rhs.0[i]))
+    }
+}
+
+/// Named unit moves; `L`/`R` along the first axis, `U`/`D` the second and `F`/`B` the third
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Command {
+    Left,
+    Right,
+    Up,
+    Down,
+    Forward,
+    Backward
+}
+impl Command {
+    /// Axis and sign of the move
+    pub fn axis(self) -> (usize, isize) {
+        match self {
+            Command::Left => (0, -1),
+            Command::Right => (0, 1),
+            Command::Up => (1, 1),
+            Command::Down => (1, -1),
+            Command::Forward => (2, 1),
+            Command::Backward => (2, -1)
+        }
+    }
+    /// Unit vector of the move; `None` when its axis is beyond `N` dimensions
+    pub fn delta<const N: usize>(self) -> Option<Point<N>> {
+        let (axis, sign) = self.axis();
+        (axis < N).then(|| Point::unit(axis, sign))
+    }
+}
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "L" => Ok(Command::Left),
+            "R" => Ok(Command::Right),
+            "U" => Ok(Command::Up),
+            "D" => Ok(Command::Down),
+            "F" => Ok(Command::Forward),
+            "B" => Ok(Command::Backward),
+            _ => Err(format!("unknown command '{s}'"))
+        }
+    }
+}
+
+/// Move the head by `delta`, `units` times; written either as a named move, e.g. `R 4`,
+/// or as an arbitrary vector with one component per axis, e.g. `<1,0,-1> 3`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Step<const N: usize> {
+    pub delta: Point<N>,
+    pub units: usize
+}
+impl<const N: usize> FromStr for Step<N> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (cmd, units) = s.trim().rsplit_once(' ').ok_or(format!("expected '<command> <units>', got '{s}'"))?;
+        let delta = match cmd.trim().strip_prefix('<').and_then(|v| v.strip_suffix('>')) {
+            Some(vector) => {
+                let components = vector.split(',')
+                    .map(|v| v.trim().parse::<isize>().map_err(|e| format!("'{s}': {e}")))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Point(components.try_into().map_err(|v: Vec<_>| format!("'{s}': expected {N} components, got {}", v.len()))?)
+            }
+            None => cmd.parse::<Command>()?.delta().ok_or(format!("'{s}': no such axis in {N} dimensions"))?
+        };
+        Ok(Step {
+            delta,
+            units: units.parse().map_err(|e| format!("'{s}': {e}"))?
+        })
+    }
+}
+/// One step per line; errors carry the line number
+pub fn parse_steps<const N: usize>(input: &str) -> Result<Vec<Step<N>>, String> {
+    input.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| line.parse().map_err(|e| format!("line {}: {e}", i + 1)))
+        .collect()
+}
+
+/// Move `knot` towards `front`, one unit along every differing axis at a time, until they are at most `slack` apart;
+/// `visit` sees every position the knot passes through
+pub fn follow<const N: usize>(mut knot: Point<N>, front: Point<N>, slack: isize, mut visit: impl FnMut(Point<N>)) -> Point<N> {
+    while knot.chebyshev(front) > slack {
+        knot += (front - knot).signum();
+        visit(knot);
+    }
+    knot
+}
+
+/// A rope of knots in `N` dimensions where each knot follows the one in front of it, with the head at index 0
+/// ```
+/// use advent2022::rope::{Pos, Rope, parse_steps};
+///
+/// let steps = parse_steps("R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2").unwrap();
+/// let mut rope = Rope::new(Pos::default(), 10).recording();
+/// rope.run(&steps);
+/// assert_eq!(rope.visited(1), 13);
+/// assert_eq!(rope.visited(9), 1);
+/// assert_eq!(rope.trail(0).unwrap().len(), 25);
+///
+/// let mut loose = Rope::new(Pos::default(), 2).with_slack(2);
+/// loose.step((3, 0).into());
+/// assert_eq!(loose.tail(), (1, 0).into());
+///
+/// let steps = parse_steps::<3>("F 2\n<1,1,0> 2").unwrap();
+/// let mut rope = Rope::new(Default::default(), 3);
+/// rope.run(&steps);
+/// assert_eq!(rope.knots(), &[(2, 2, 2).into(), (1, 1, 2).into(), (1, 1, 1).into()]);
+/// ```
+pub struct Rope<const N: usize> {
+    knots: Vec<Point<N>>,
+    slack: isize,
+    trails: Option<Vec<Vec<Point<N>>>>,
+    visited: Vec<HashSet<Point<N>>>
+}
+impl<const N: usize> Rope<N> {
+    /// A rope of `len` knots all starting at `start`; adjacent knots must touch, as in the puzzle
+    pub fn new(start: Point<N>, len: usize) -> Rope<N> {
+        assert!(len > 0, "a rope needs at least one knot");
+        Rope {
+            knots: vec![start; len],
+            slack: 1,
+            trails: None,
+            visited: vec![HashSet::from([start]); len]
+        }
+    }
+    /// Let adjacent knots drift up to `slack` units apart along any axis before the follower moves
+    pub fn with_slack(mut self, slack: usize) -> Rope<N> {
+        self.slack = slack as isize;
+        self
+    }
+    /// Keep every knot's position after each step
+    pub fn recording(mut self) -> Rope<N> {
+        self.trails = Some(self.knots.iter().map(|&p| vec![p]).collect());
+        self
+    }
+    pub fn knots(&self) -> &[Point<N>] {
+        &self.knots
+    }
+    pub fn head(&self) -> Point<N> {
+        self.knots[0]
+    }
+    pub fn tail(&self) -> Point<N> {
+        self.knots[self.knots.len() - 1]
+    }
+    /// Move the head by `delta` and let every other knot catch up; returns the tail's new position
+    pub fn step(&mut self, delta: Point<N>) -> Point<N> {
+        self.knots[0] += delta;
+        self.visited[0].insert(self.knots[0]);
+        for i in 1..self.knots.len() {
+            let visited = &mut self.visited[i];
+            self.knots[i] = follow(self.knots[i], self.knots[i - 1], self.slack, |p| { visited.insert(p); });
+        }
+        if let Some(trails) = self.trails.as_mut() {
+            trails.iter_mut().zip(&self.knots).for_each(|(trail, &p)| trail.push(p));
+        }
+        self.tail()
+    }
+    pub fn run(&mut self, steps: &[Step<N>]) -> &mut Self {
+        steps.iter()
+            .flat_map(|s| std::iter::repeat_n(s.delta, s.units))
+            .for_each(|delta| { self.step(delta); });
+        self
+    }
+    /// Positions of the knot after every step, starting with its initial one; `None` unless recording
+    pub fn trail(&self, knot: usize) -> Option<&[Point<N>]> {
+        self.trails.as_ref()?.get(knot).map(Vec::as_slice)
+    }
+    /// Number of distinct positions the knot has occupied
+    pub fn visited(&self, knot: usize) -> usize {
+        self.visited[knot].len()
+    }
+    /// Distinct positions the knot has occupied, in no particular order
+    pub fn visited_cells(&self, knot: usize) -> impl Iterator<Item=Point<N>> + '_ {
+        self.visited[knot].iter().copied()
+    }
+    /// Distinct positions per knot, head first
+    pub fn visits(&self) -> Vec<usize> {
+        self.visited.iter().map(HashSet::len).collect()
+    }
+    /// Recorded trails as `step,knot,x,y,...` rows, one coordinate column per axis; `None` unless recording
+    pub fn trace_csv(&self) -> Option<String> {
+        let trails = self.trails.as_ref()?;
+        let steps = trails[0].len();
+        let axes = ["x", "y", "z", "w"].into_iter()
+            .map(String::from)
+            .chain((4..).map(|i| format!("x{i}")))
+            .take(N)
+            .collect::<Vec<_>>();
+        Some((0..steps)
+            .flat_map(|s| trails.iter().enumerate().map(move |(k, trail)| (s, k, trail[s])))
+            .fold(format!("step,knot,{}\n", axes.join(",")), |mut out, (s, k, p)| {
+                out.push_str(&format!("{s},{k},{}\n", p.0.map(|v| v.to_string()).join(",")));
+                out
+            }))
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt::{Debug, Display, Formatter};
+use std::ops::BitAnd;
+use std::str::FromStr;
+
+/// An item character that falls outside `'a'..='z'` and `'A'..='Z'`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InvalidItem {
+    pub item: char,
+    pub pos: usize
+}
+impl Display for InvalidItem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid item '{}' at position {}", self.item, self.pos)
+    }
+}
+impl std::error::Error for InvalidItem {}
+
+/// Set of rucksack items encoded as a 52-bit mask, where bit `n` stands for the item with priority `n+1`
+/// ```
+/// use advent2022::rucksack::Rucksack;
+///
+/// let (a, b) = Rucksack::compartments("vJrwpWtwJgWrhcsFMMfFFhFp").unwrap();
+/// assert_eq!((a & b).priority_sum(), 16);
+/// assert_eq!(Rucksack::group(["vJrwpWtwJgWrhcsFMMfFFhFp", "jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL", "PmmdzqPrVvPwwTWBwg"]).unwrap().priority_sum(), 18);
+/// assert!("abc-".parse::<Rucksack>().is_err());
+/// ```
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Rucksack(u64);
+
+impl Rucksack {
+    /// Priority of an item; `a..z` map to 1..26 and `A..Z` to 27..52
+    pub fn priority(item: char) -> Option<u32> {
+        match item {
+            'a'..='z' => Some(u32::from(item) - u32::from('a') + 1),
+            'A'..='Z' => Some(u32::from(item) - u32::from('A') + 27),
+            _ => None
+        }
+    }
+    /// Splits a line in two halves of equal item count and returns the items of each compartment;
+    /// error positions count characters from the start of the line
+    pub fn compartments(line: &str) -> Result<(Rucksack, Rucksack), InvalidItem> {
+        let half = line.chars().count() / 2;
+        let mid = line.char_indices().nth(half).map_or(line.len(), |(i, _)| i);
+        let (a, b) = line.split_at(mid);
+        Ok((a.parse()?, Rucksack::from_str(b).map_err(|e| InvalidItem { pos: e.pos + half, ..e })?))
+    }
+    /// Items common to all rucksacks of a group of any size
+    pub fn group<'a>(lines: impl IntoIterator<Item=&'a str>) -> Result<Rucksack, InvalidItem> {
+        lines.into_iter()
+            .map(Rucksack::from_str)
+            .reduce(|a, b| Ok(a? & b?))
+            .unwrap_or(Ok(Rucksack::default()))
+    }
+    pub fn contains(&self, item: char) -> bool {
+        Rucksack::priority(item).is_some_and(|p| self.0 & (1 << (p - 1)) != 0)
+    }
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+    /// Priorities of the items held, in ascending order
+    pub fn priorities(&self) -> impl Iterator<Item=u32> {
+        let mut mask = self.0;
+        std::iter::from_fn(move || {
+            if mask == 0 { return None }
+            let p = mask.trailing_zeros();
+            mask &= mask - 1;
+            Some(p + 1)
+        })
+    }
+    pub fn priority_sum(&self) -> u32 {
+        self.priorities().sum()
+    }
+}
+impl FromStr for Rucksack {
+    type Err = InvalidItem;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.chars()
+            .enumerate()
+            .try_fold(Rucksack::default(), |Rucksack(mask), (pos, item)|
+                Rucksack::priority(item)
+                    .map(|p| Rucksack(mask | 1 << (p - 1)))
+                    .ok_or(InvalidItem { item, pos })
+            )
+    }
+}
+impl BitAnd for Rucksack {
+    type Output = Rucksack;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Rucksack(self.0 & rhs.0)
+    }
+}
+impl Debug for Rucksack {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let items = self.priorities()
+            .map(|p| match p {
+                1..=26 => char::from(b'a' + (p - 1) as u8),
+                _ => char::from(b'A' + (p - 27) as u8)
+            })
+            .collect::<String>();
+        write!(f, "Rucksack({items})")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compartments_non_ascii() {
+        assert_eq!(Rucksack::compartments("éa"), Err(InvalidItem { item: 'é', pos: 0 }));
+        assert_eq!(Rucksack::compartments("abéa"), Err(InvalidItem { item: 'é', pos: 2 }));
+        assert_eq!(Rucksack::compartments("aaé€"), Err(InvalidItem { item: 'é', pos: 2 }));
+        let (a, b) = Rucksack::compartments("abcb").unwrap();
+        assert_eq!((a & b).priority_sum(), 2);
+    }
+}